@@ -0,0 +1,185 @@
+//! Benchmarks of drawing onto a TermRect, and of rendering its changes
+//! through a Terminal into a Vec<u8>.
+//!
+//! Each scenario is benchmarked twice: "draw" is just the draws onto the
+//! TermRect, and "render" also writes the delta out. The render benchmark's
+//! throughput is the bytes of output per frame, which is printed too, so a
+//! change in how much is written shows up as well as a change in time.
+
+#[macro_use]
+extern crate criterion;
+extern crate termrect;
+
+use criterion::{black_box, Criterion, Throughput};
+use termrect::terminal::Terminal;
+use termrect::termrect::{HasSize, PaintableWidget, RawPaintable};
+use termrect::{Color, Style, StyleAttr, StyledText, TermRect};
+
+const SIZE: (u32, u32) = (120, 40);
+
+const WORDS: &[&str] = &[
+    "lorem", "ipsum", "dolor", "sit", "amet", "render", "delta", "span", "line", "cell",
+];
+const WIDE_WORDS: &[&str] = &["台北", "東京", "서울", "漢字", "テスト", "表示"];
+
+/// A small LCG, so every run draws the same things.
+struct Rng(u64);
+
+impl Rng {
+    fn below(&mut self, n: u32) -> u32 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        ((self.0 >> 33) % n as u64) as u32
+    }
+
+    fn pick<'a>(&mut self, words: &[&'a str]) -> &'a str {
+        words[self.below(words.len() as u32) as usize]
+    }
+}
+
+fn style(i: u32) -> Style {
+    let style = Style::default().set_fg(Color::Indexed((i % 8) as u16));
+    match i % 3 {
+        0 => style.set(StyleAttr::Bold),
+        _ => style,
+    }
+}
+
+/// A scenario draws one frame onto the TermRect.
+type Scenario = fn(&mut TermRect, &mut Rng, u32);
+
+/// Every line rewritten, in a few styled spans.
+fn full_redraw(tr: &mut TermRect, rng: &mut Rng, frame: u32) {
+    for y in 0..SIZE.1 {
+        let mut x = 0;
+        let mut i = frame + y;
+        while x < SIZE.0 {
+            let text = format!("{} {} ", rng.pick(WORDS), i);
+            let text = StyledText::new(style(i), text);
+            tr.draw_text_at((x, y), &text);
+            x += text.size().0;
+            i += 1;
+        }
+    }
+}
+
+/// A clock, a counter and a cell or two changing on a full screen.
+fn sparse_updates(tr: &mut TermRect, rng: &mut Rng, frame: u32) {
+    let clock = format!("{:02}:{:02}", frame / 60 % 60, frame % 60);
+    tr.draw_str_at((SIZE.0 - 5, 0), style(1), clock);
+    let counter = format!("{} items", frame);
+    tr.draw_str_at((0, SIZE.1 - 1), style(2), counter);
+    for _ in 0..2 {
+        let pos = (rng.below(SIZE.0), rng.below(SIZE.1));
+        tr.draw_str_at(pos, style(frame), "*".to_string());
+    }
+}
+
+/// A log scrolling up by a line each frame, with lines of varying length.
+fn scrolling_log(tr: &mut TermRect, _: &mut Rng, frame: u32) {
+    for y in 0..SIZE.1 {
+        let n = frame + y;
+        let len = (n * 7 % 13 + 1) as usize;
+        let msg = WORDS.iter().cycle().skip(n as usize).take(len);
+        let msg = msg.cloned().collect::<Vec<_>>().join(" ");
+        let line = format!("[{:>6}] {}", n, msg);
+        tr.draw_str_at((0, y), style(n % 4), line.clone());
+        let end = line.len() as u32;
+        if end < SIZE.0 {
+            tr.clear_to_end_of_line((end, y), Style::default());
+        }
+    }
+}
+
+/// Single cells written at random places in random styles.
+fn random_cells(tr: &mut TermRect, rng: &mut Rng, _: u32) {
+    for _ in 0..200 {
+        let pos = (rng.below(SIZE.0), rng.below(SIZE.1));
+        let c = (b'a' + rng.below(26) as u8) as char;
+        tr.draw_str_at(pos, style(rng.below(16)), c.to_string());
+    }
+}
+
+/// Double width text at random offsets, cutting through what's there.
+fn wide_chars(tr: &mut TermRect, rng: &mut Rng, frame: u32) {
+    for y in 0..SIZE.1 {
+        let x = rng.below(SIZE.0);
+        let text: Vec<_> = (0..8).map(|_| rng.pick(WIDE_WORDS)).collect();
+        tr.draw_str_at((x, y), style(frame + y), text.join("a"));
+    }
+}
+
+/// A screen full of text, with nothing marked changed.
+fn filled() -> TermRect {
+    let mut tr = TermRect::new(SIZE);
+    full_redraw(&mut tr, &mut Rng(1), 0);
+    tr.mark_none_changed();
+    tr
+}
+
+fn terminal() -> Terminal<Vec<u8>> {
+    let mut term = Terminal::new(Vec::new(), SIZE);
+    filled().draw_into(&mut term, (0, 0));
+    term.get_mut().clear();
+    term
+}
+
+/// Draw a frame and write its changes out, returning how many bytes that
+/// was.
+fn render(
+    scenario: Scenario,
+    tr: &mut TermRect,
+    term: &mut Terminal<Vec<u8>>,
+    rng: &mut Rng,
+    frame: u32,
+) -> usize {
+    term.get_mut().clear();
+    scenario(tr, rng, frame);
+    tr.draw_delta_into(term, (0, 0));
+    term.flush().unwrap();
+    term.get_ref().len()
+}
+
+fn bench_scenario(c: &mut Criterion, name: &str, scenario: Scenario) {
+    let mut group = c.benchmark_group(name);
+
+    group.bench_function("draw", |b| {
+        let (mut tr, mut rng, mut frame) = (filled(), Rng(2), 0);
+        b.iter(|| {
+            frame += 1;
+            scenario(&mut tr, &mut rng, frame);
+            tr.mark_none_changed();
+        })
+    });
+
+    let (mut tr, mut term, mut rng) = (filled(), terminal(), Rng(2));
+    let frames = 100;
+    let bytes: usize = (1..=frames)
+        .map(|frame| render(scenario, &mut tr, &mut term, &mut rng, frame))
+        .sum();
+    let per_frame = bytes as u64 / frames as u64;
+    println!("{}: {} bytes per frame", name, per_frame);
+    group.throughput(Throughput::Bytes(per_frame));
+
+    group.bench_function("render", |b| {
+        let (mut tr, mut term, mut rng, mut frame) = (filled(), terminal(), Rng(2), 0);
+        b.iter(|| {
+            frame += 1;
+            black_box(render(scenario, &mut tr, &mut term, &mut rng, frame))
+        })
+    });
+    group.finish();
+}
+
+fn rendering(c: &mut Criterion) {
+    bench_scenario(c, "full_redraw", full_redraw);
+    bench_scenario(c, "sparse_updates", sparse_updates);
+    bench_scenario(c, "scrolling_log", scrolling_log);
+    bench_scenario(c, "random_cells", random_cells);
+    bench_scenario(c, "wide_chars", wide_chars);
+}
+
+criterion_group!(benches, rendering);
+criterion_main!(benches);
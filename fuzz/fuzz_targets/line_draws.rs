@@ -0,0 +1,10 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use termrect::fuzzing::{check_line_ops, ops_from_bytes};
+
+// Random draws on a Line, checked against a naive model of its cells.
+fuzz_target!(|data: &[u8]| {
+    let (width, ops) = ops_from_bytes(data);
+    check_line_ops(width, &ops);
+});
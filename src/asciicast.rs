@@ -0,0 +1,530 @@
+//! Recording and replaying asciicast v2 files, as made by asciinema.
+//!
+//! A cast is a header line, a JSON object with the terminal size, followed
+//! by one line per event, a JSON array of the time in seconds, the event
+//! type ("o" for output, "i" for input, "r" for resize) and its data.
+
+use termrect::{is_sane_size, TermRect};
+use vt::Emulator;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// Write s as a JSON string.
+fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 || c == '\x7f' => {
+                out.push_str(&format!("\\u{:04x}", c as u32));
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// CastWriter records everything written through it as output events of an
+/// asciicast, while passing it on to the inner writer. Put it inside a
+/// Terminal to record a session:
+///
+/// ```no_run
+/// # use termrect::asciicast::CastWriter;
+/// # use std::{fs::File, io};
+/// let cast = File::create("session.cast").unwrap();
+/// let w = CastWriter::new(io::stdout(), cast, (80, 24)).unwrap();
+/// ```
+pub struct CastWriter<W: Write, C: Write> {
+    inner: W,
+    cast: C,
+    start: Instant,
+    // The start of a UTF-8 char cut off at the end of the last write.
+    partial: Vec<u8>,
+}
+
+impl<W: Write, C: Write> CastWriter<W, C> {
+    /// Start a cast of a terminal of size, writing the header to cast.
+    pub fn new(inner: W, mut cast: C, size: (u32, u32)) -> io::Result<CastWriter<W, C>> {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        writeln!(
+            cast,
+            "{{\"version\": 2, \"width\": {}, \"height\": {}, \"timestamp\": {}}}",
+            size.0, size.1, timestamp
+        )?;
+        Ok(CastWriter {
+            inner,
+            cast,
+            start: Instant::now(),
+            partial: Vec::new(),
+        })
+    }
+
+    /// Record that the terminal was resized.
+    pub fn resize(&mut self, size: (u32, u32)) -> io::Result<()> {
+        self.event("r", &format!("{}x{}", size.0, size.1))
+    }
+
+    /// Record input, such as keys pressed.
+    pub fn input(&mut self, data: &str) -> io::Result<()> {
+        self.event("i", data)
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Give back the inner writer and the cast.
+    pub fn into_inner(self) -> (W, C) {
+        (self.inner, self.cast)
+    }
+
+    fn event(&mut self, kind: &str, data: &str) -> io::Result<()> {
+        let mut line = format!("[{:.6}, ", self.start.elapsed().as_secs_f64());
+        write_json_string(&mut line, kind);
+        line.push_str(", ");
+        write_json_string(&mut line, data);
+        line.push_str("]\n");
+        self.cast.write_all(line.as_bytes())
+    }
+}
+
+impl<W: Write, C: Write> Write for CastWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.partial.extend_from_slice(&buf[..n]);
+        // Hold back an incomplete char at the end until the rest arrives.
+        let valid = match std::str::from_utf8(&self.partial) {
+            Ok(_) => self.partial.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => self.partial.len(),
+        };
+        if valid > 0 {
+            let bytes: Vec<u8> = self.partial.drain(..valid).collect();
+            self.event("o", &String::from_utf8_lossy(&bytes))?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+        self.cast.flush()
+    }
+}
+
+/// One event of a cast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CastEvent {
+    /// Seconds since the start.
+    pub time: f64,
+    /// "o" for output, "i" for input, "r" for resize.
+    pub kind: String,
+    pub data: String,
+}
+
+/// Cast is an asciicast read back in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cast {
+    pub size: (u32, u32),
+    pub events: Vec<CastEvent>,
+}
+
+/// CastError says what was wrong with a cast, and on which line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CastError {
+    pub message: String,
+    /// The line number, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for CastError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CastError {}
+
+impl Cast {
+    pub fn parse(text: &str) -> Result<Cast, CastError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .filter(|(_, l)| !l.trim().is_empty());
+        let (_, header) = lines.next().ok_or_else(|| CastError {
+            message: "no header".to_string(),
+            line: 1,
+        })?;
+        let error = |line: usize, message: &str| CastError {
+            message: message.to_string(),
+            line: line + 1,
+        };
+        let header = Json::parse(header).map_err(|e| error(0, &e))?;
+        if header.get("version").and_then(Json::as_u32) != Some(2) {
+            return Err(error(0, "not an asciicast v2 file"));
+        }
+        let dim = |name| {
+            header
+                .get(name)
+                .and_then(Json::as_u32)
+                .ok_or_else(|| error(0, &format!("no {} in header", name)))
+        };
+        let size = (dim("width")?, dim("height")?);
+        if !is_sane_size(size) {
+            return Err(error(0, &format!("size {}x{} is too big", size.0, size.1)));
+        }
+
+        let mut events = Vec::new();
+        for (i, line) in lines {
+            match Json::parse(line).map_err(|e| error(i, &e))? {
+                Json::Array(ref a) => match a[..] {
+                    [Json::Number(time), Json::String(ref kind), Json::String(ref data)] => events
+                        .push(CastEvent {
+                            time,
+                            kind: kind.clone(),
+                            data: data.clone(),
+                        }),
+                    _ => return Err(error(i, "expected [time, type, data]")),
+                },
+                _ => return Err(error(i, "expected [time, type, data]")),
+            }
+        }
+        Ok(Cast { size, events })
+    }
+
+    /// Play the whole cast, returning the screen at the end.
+    pub fn replay(&self) -> TermRect {
+        self.replay_until(f64::INFINITY)
+    }
+
+    /// Play the events up to and including time, returning the screen then.
+    pub fn replay_until(&self, time: f64) -> TermRect {
+        let mut emulator = Emulator::new(self.size);
+        for e in self.events.iter().take_while(|e| e.time <= time) {
+            match &e.kind as &str {
+                "o" => emulator.feed(&e.data),
+                "r" => {
+                    let mut dims = e.data.split('x').map(|d| d.parse::<u32>());
+                    if let (Some(Ok(w)), Some(Ok(h))) = (dims.next(), dims.next()) {
+                        // Sizes too big to be real are left out.
+                        if is_sane_size((w, h)) {
+                            emulator.resize((w, h));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        emulator.into_screen()
+    }
+}
+
+impl FromStr for Cast {
+    type Err = CastError;
+
+    fn from_str(s: &str) -> Result<Cast, CastError> {
+        Cast::parse(s)
+    }
+}
+
+/// How deeply arrays and objects can nest. Casts only need 2, and the parser
+/// recurses, so anything deeper is refused rather than overflowing the stack.
+const MAX_DEPTH: usize = 16;
+
+/// Just enough JSON to read casts.
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut chars = text.trim().chars().peekable();
+        let value = Json::value(&mut chars, 0)?;
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected {:?} after JSON value", c)),
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<&Json> {
+        match *self {
+            Json::Object(ref fields) => fields.iter().find(|f| f.0 == key).map(|f| &f.1),
+            _ => None,
+        }
+    }
+
+    fn as_u32(&self) -> Option<u32> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 && n <= u32::MAX as f64 => {
+                Some(n as u32)
+            }
+            _ => None,
+        }
+    }
+
+    fn value<I: Iterator<Item = char>>(
+        chars: &mut std::iter::Peekable<I>,
+        depth: usize,
+    ) -> Result<Json, String> {
+        skip_space(chars);
+        if depth > MAX_DEPTH && (chars.peek() == Some(&'[') || chars.peek() == Some(&'{')) {
+            return Err("JSON nested too deeply".to_string());
+        }
+        let value = match chars.peek().cloned() {
+            Some('{') => {
+                chars.next();
+                let mut fields = Vec::new();
+                skip_space(chars);
+                if chars.peek() == Some(&'}') {
+                    chars.next();
+                } else {
+                    loop {
+                        skip_space(chars);
+                        let key = match Json::value(chars, depth + 1)? {
+                            Json::String(key) => key,
+                            _ => return Err("expected a string key".to_string()),
+                        };
+                        skip_space(chars);
+                        expect(chars, ':')?;
+                        fields.push((key, Json::value(chars, depth + 1)?));
+                        skip_space(chars);
+                        match chars.next() {
+                            Some(',') => {}
+                            Some('}') => break,
+                            _ => return Err("expected , or }".to_string()),
+                        }
+                    }
+                }
+                Json::Object(fields)
+            }
+            Some('[') => {
+                chars.next();
+                let mut items = Vec::new();
+                skip_space(chars);
+                if chars.peek() == Some(&']') {
+                    chars.next();
+                } else {
+                    loop {
+                        items.push(Json::value(chars, depth + 1)?);
+                        skip_space(chars);
+                        match chars.next() {
+                            Some(',') => {}
+                            Some(']') => break,
+                            _ => return Err("expected , or ]".to_string()),
+                        }
+                    }
+                }
+                Json::Array(items)
+            }
+            Some('"') => {
+                chars.next();
+                Json::String(string(chars)?)
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => {
+                let mut num = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || "+-.eE".contains(c) {
+                        num.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                Json::Number(num.parse().map_err(|_| format!("bad number {:?}", num))?)
+            }
+            Some(_) => {
+                let word: String =
+                    std::iter::from_fn(|| chars.next_if(|c| c.is_ascii_alphabetic())).collect();
+                match &word as &str {
+                    "null" => Json::Null,
+                    "true" => Json::Bool(true),
+                    "false" => Json::Bool(false),
+                    _ => return Err(format!("unexpected {:?}", word)),
+                }
+            }
+            None => return Err("unexpected end of line".to_string()),
+        };
+        Ok(value)
+    }
+}
+
+fn skip_space<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) {
+    while chars.next_if(|c| c.is_whitespace()).is_some() {}
+}
+
+fn expect<I: Iterator<Item = char>>(
+    chars: &mut std::iter::Peekable<I>,
+    want: char,
+) -> Result<(), String> {
+    match chars.next() {
+        Some(c) if c == want => Ok(()),
+        _ => Err(format!("expected {:?}", want)),
+    }
+}
+
+/// The rest of a string, after the opening quote.
+fn string<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> Result<String, String> {
+    let mut s = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(s),
+            Some('\\') => {
+                let c = match chars.next() {
+                    Some('n') => '\n',
+                    Some('r') => '\r',
+                    Some('t') => '\t',
+                    Some('b') => '\x08',
+                    Some('f') => '\x0c',
+                    Some('u') => {
+                        let mut code = hex4(chars)?;
+                        // A surrogate pair
+                        if (0xd800..0xdc00).contains(&code) {
+                            expect(chars, '\\')?;
+                            expect(chars, 'u')?;
+                            let low = hex4(chars)?;
+                            code = 0x10000
+                                + ((code - 0xd800) << 10)
+                                + (low.wrapping_sub(0xdc00) & 0x3ff);
+                        }
+                        std::char::from_u32(code).unwrap_or('\u{fffd}')
+                    }
+                    Some(c) => c,
+                    None => return Err("unterminated string".to_string()),
+                };
+                s.push(c);
+            }
+            Some(c) => s.push(c),
+            None => return Err("unterminated string".to_string()),
+        }
+    }
+}
+
+fn hex4<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> Result<u32, String> {
+    let digits: String = chars.take(4).collect();
+    u32::from_str_radix(&digits, 16).map_err(|_| format!("bad escape \\u{}", digits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::render_snapshot;
+
+    #[test]
+    fn record_and_replay() {
+        let mut w = CastWriter::new(Vec::new(), Vec::new(), (10, 2)).unwrap();
+        w.write_all(b"\x1b[2;1Hsay \"hi\"\\").unwrap();
+        // a char split across writes is recorded whole
+        w.write_all(&"台".as_bytes()[..1]).unwrap();
+        w.write_all(&"台".as_bytes()[1..]).unwrap();
+        w.resize((12, 3)).unwrap();
+        let (out, cast) = w.into_inner();
+        assert_eq!(out, "\x1b[2;1Hsay \"hi\"\\台".as_bytes());
+
+        let cast = String::from_utf8(cast).unwrap();
+        let lines: Vec<_> = cast.lines().collect();
+        assert!(lines[0].starts_with("{\"version\": 2, \"width\": 10, \"height\": 2,"));
+        assert!(lines[1].ends_with(", \"o\", \"\\u001b[2;1Hsay \\\"hi\\\"\\\\\"]"));
+        assert!(lines[2].ends_with(", \"o\", \"台\"]"));
+        assert!(lines[3].ends_with(", \"r\", \"12x3\"]"));
+
+        let cast: Cast = cast.parse().unwrap();
+        assert_eq!(cast.size, (10, 2));
+        assert_eq!(cast.events.len(), 3);
+        let screen = cast.replay();
+        // 台 didn't fit on the line, so wrapped and scrolled
+        assert_eq!(
+            render_snapshot(&screen),
+            "12x3\n|say \"hi\"\\   |\n|台          |\n|            |\ncursor 2,1\n"
+        );
+        assert_eq!(
+            render_snapshot(&cast.replay_until(-1.0)),
+            "10x2\n|          |\n|          |\n"
+        );
+    }
+
+    #[test]
+    fn parsing() {
+        let cast = Cast::parse(
+            "{\"version\": 2, \"width\": 4, \"height\": 1, \"env\": {\"TERM\": null}}\n\
+             [0.5, \"o\", \"\\u00e9\\ud83d\\ude00\"]\n\n",
+        )
+        .unwrap();
+        assert_eq!(cast.events[0].time, 0.5);
+        assert_eq!(cast.events[0].data, "é😀");
+
+        assert_eq!(
+            Cast::parse("{\"version\": 1}").unwrap_err().to_string(),
+            "line 1: not an asciicast v2 file"
+        );
+        let err =
+            Cast::parse("{\"version\": 2, \"width\": 4, \"height\": 1}\n[1, \"o\"]").unwrap_err();
+        assert_eq!(err.line, 2);
+
+        let header = "{\"version\": 2, \"width\": 4, \"height\": 1}\n";
+        let deep = format!("{}{}{}", header, "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(
+            Cast::parse(&deep).unwrap_err().to_string(),
+            "line 2: JSON nested too deeply"
+        );
+        let huge = "{\"version\": 2, \"width\": 4294967295, \"height\": 1}";
+        assert_eq!(
+            Cast::parse(huge).unwrap_err().to_string(),
+            "line 1: size 4294967295x1 is too big"
+        );
+        let resize = format!("{}[1, \"r\", \"4294967295x4294967295\"]", header);
+        let screen = Cast::parse(&resize).unwrap().replay();
+        assert_eq!(render_snapshot(&screen), "4x1\n|    |\n");
+        let nested = format!("{}[1, \"o\", [\"a\"]]", header);
+        assert_eq!(
+            Cast::parse(&nested).unwrap_err().to_string(),
+            "line 2: expected [time, type, data]"
+        );
+    }
+
+    #[cfg(feature = "termion")]
+    #[test]
+    fn terminal() {
+        use style::{Color, Style};
+        use styledtext::StyledText;
+        use terminal::Terminal;
+        use termrect::{PaintableWidget, RawPaintable};
+
+        let mut tr = TermRect::new((8, 2));
+        let red = Style::default().set_fg(Color::Indexed(1));
+        tr.draw_text_at((1, 0), &StyledText::new(red, "ab    cd".to_string()));
+        tr.draw_text_at(
+            (0, 1),
+            &StyledText::new(Style::default(), "xxxxxx".to_string()),
+        );
+
+        let w = CastWriter::new(io::sink(), Vec::new(), (8, 2)).unwrap();
+        let mut term = Terminal::new(w, (8, 2));
+        tr.draw_into(&mut term, (0, 0));
+        term.flush().unwrap();
+        let cast = String::from_utf8(term.into_inner().into_inner().1).unwrap();
+        let screen = Cast::parse(&cast).unwrap().replay();
+        assert_eq!(
+            render_snapshot(&screen),
+            "8x2\n| [red]ab    c[/]|\n|xxxxxx  |\ncursor 6,1\n"
+        );
+    }
+}
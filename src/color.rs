@@ -0,0 +1,333 @@
+use style::Color;
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// The names of the 16 ANSI colors, in index order.
+pub(crate) const ANSI_NAMES: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// The CSS named colors, which are mostly the X11 ones, sorted by name.
+const NAMED_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+/// ParseColorError is returned for a string that isn't a color.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseColorError {
+    pub text: String,
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid color {:?}", self.text)
+    }
+}
+
+impl Error for ParseColorError {}
+
+/// Lower case, with spaces, dashes and underscores removed, so
+/// "Light Sea-Green" finds "lightseagreen".
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| !matches!(c, ' ' | '-' | '_'))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn parse_hex(hex: &str) -> Option<Color> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).unwrap();
+    let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    match hex.len() {
+        3 => Some(Color::RGB(digit(0) * 17, digit(1) * 17, digit(2) * 17)),
+        6 => Some(Color::RGB(byte(0), byte(2), byte(4))),
+        _ => None,
+    }
+}
+
+/// The arguments of "name(...)", split at commas.
+fn call_args<'a>(s: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let args = s.strip_prefix(name)?.trim_start().strip_prefix('(')?;
+    let args = args.strip_suffix(')')?;
+    Some(args.split(',').map(str::trim).collect())
+}
+
+impl FromStr for Color {
+    type Err = ParseColorError;
+
+    /// Parse "default", "#rrggbb", "#rgb", "rgb(r, g, b)", "ansi(n)", one of
+    /// the 16 ANSI color names such as "red" or "bright_red", or a CSS color
+    /// name such as "cornflowerblue". The ANSI names win over the CSS names
+    /// they share.
+    fn from_str(s: &str) -> Result<Color, ParseColorError> {
+        let s = s.trim();
+        let color = if let Some(hex) = s.strip_prefix('#') {
+            parse_hex(hex)
+        } else if let Some(args) = call_args(s, "rgb") {
+            match args[..] {
+                [r, g, b] => match (r.parse(), g.parse(), b.parse()) {
+                    (Ok(r), Ok(g), Ok(b)) => Some(Color::RGB(r, g, b)),
+                    _ => None,
+                },
+                _ => None,
+            }
+        } else if let Some(args) = call_args(s, "ansi") {
+            match args[..] {
+                [n] => n.parse().ok().map(Color::Indexed),
+                _ => None,
+            }
+        } else {
+            let name = normalize(s);
+            if name == "default" {
+                Some(Color::Default)
+            } else if let Some(i) = ANSI_NAMES.iter().position(|n| normalize(n) == name) {
+                Some(Color::Indexed(i as u16))
+            } else {
+                NAMED_COLORS
+                    .binary_search_by(|&(n, _)| n.cmp(&name))
+                    .ok()
+                    .map(|i| {
+                        let rgb = NAMED_COLORS[i].1;
+                        Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+                    })
+            }
+        };
+        color.ok_or_else(|| ParseColorError {
+            text: s.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for Color {
+    /// Write the color so that parsing it gives it back: "default", an ANSI
+    /// name, "ansi(n)" or "#rrggbb".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Color::Default => write!(f, "default"),
+            Color::Indexed(i) if (i as usize) < ANSI_NAMES.len() => {
+                write!(f, "{}", ANSI_NAMES[i as usize])
+            }
+            Color::Indexed(i) => write!(f, "ansi({})", i),
+            Color::RGB(r, g, b) => write!(f, "#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parsing() {
+        let parse = |s: &str| s.parse::<Color>();
+        assert_eq!(parse("#ff8000"), Ok(Color::RGB(255, 128, 0)));
+        assert_eq!(parse("#F80"), Ok(Color::RGB(255, 136, 0)));
+        assert_eq!(parse("rgb(1, 2,3)"), Ok(Color::RGB(1, 2, 3)));
+        assert_eq!(parse("ansi(208)"), Ok(Color::Indexed(208)));
+        assert_eq!(parse("red"), Ok(Color::Indexed(1)));
+        assert_eq!(parse("Bright Red"), Ok(Color::Indexed(9)));
+        assert_eq!(parse("Default"), Ok(Color::Default));
+        assert_eq!(parse("cornflowerblue"), Ok(Color::RGB(0x64, 0x95, 0xed)));
+        assert_eq!(parse("light-sea-green"), Ok(Color::RGB(0x20, 0xb2, 0xaa)));
+
+        for bad in &[
+            "",
+            "#12",
+            "#ggg",
+            "rgb(1,2)",
+            "rgb(1,2,300)",
+            "ansi()",
+            "blurple",
+        ] {
+            assert_eq!(
+                parse(bad),
+                Err(ParseColorError {
+                    text: bad.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for c in &[
+            Color::Default,
+            Color::Indexed(3),
+            Color::Indexed(12),
+            Color::Indexed(100),
+            Color::RGB(0, 128, 255),
+        ] {
+            assert_eq!(c.to_string().parse::<Color>(), Ok(*c));
+        }
+        assert_eq!(Color::Indexed(12).to_string(), "bright_blue");
+        assert_eq!(Color::Indexed(100).to_string(), "ansi(100)");
+        assert_eq!(Color::RGB(0, 128, 255).to_string(), "#0080ff");
+    }
+}
@@ -0,0 +1,353 @@
+use cursor::Cursor;
+use line::Cell;
+use style::Style;
+use styledtext::StyledText;
+use termrect::{HasSize, PaintableWidget, RawPaintable, TermRect};
+
+/// Identifies a layer added to a Compositor.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LayerId(u32);
+
+#[derive(Debug)]
+struct Layer {
+    id: LayerId,
+    rect: TermRect,
+    pos: (u32, u32),
+    z: i32,
+    visible: bool,
+}
+
+/// Compositor stacks TermRect layers on top of each other, for popups,
+/// tooltips and menus over the rest of the screen. Layers with a higher z are
+/// on top, and transparent text (see StyledText::transparent) lets the layers
+/// below show through.
+///
+/// draw_delta_into compares the combined result with what it drew last time,
+/// and only redraws cells that look different.
+#[derive(Debug)]
+pub struct Compositor {
+    size: (u32, u32),
+    // Kept sorted by z, and in the order they were added for equal z.
+    layers: Vec<Layer>,
+    next_id: u32,
+    // What the target was last left showing, or None if it's unknown.
+    shown: Option<Vec<Vec<Cell>>>,
+    shown_cursor: Option<Cursor>,
+    // Whether layers were added, moved, etc. since the last draw_delta_into.
+    changed: bool,
+}
+
+impl Compositor {
+    pub fn new(size: (u32, u32)) -> Compositor {
+        Compositor {
+            size,
+            layers: Vec::new(),
+            next_id: 0,
+            shown: None,
+            shown_cursor: None,
+            changed: false,
+        }
+    }
+
+    /// Add a layer with its top left corner at pos.
+    pub fn add_layer(&mut self, rect: TermRect, pos: (u32, u32), z: i32) -> LayerId {
+        let id = LayerId(self.next_id);
+        self.next_id += 1;
+        self.layers.push(Layer {
+            id,
+            rect,
+            pos,
+            z,
+            visible: true,
+        });
+        self.sort();
+        id
+    }
+
+    /// Remove the layer, returning its TermRect.
+    pub fn remove_layer(&mut self, id: LayerId) -> Option<TermRect> {
+        let i = self.layers.iter().position(|l| l.id == id)?;
+        self.changed = true;
+        Some(self.layers.remove(i).rect)
+    }
+
+    pub fn layer(&self, id: LayerId) -> Option<&TermRect> {
+        self.layers.iter().find(|l| l.id == id).map(|l| &l.rect)
+    }
+
+    /// The layer's TermRect, to draw into.
+    pub fn layer_mut(&mut self, id: LayerId) -> Option<&mut TermRect> {
+        self.find_mut(id).map(|l| &mut l.rect)
+    }
+
+    /// Move the layer's top left corner to pos.
+    pub fn set_position(&mut self, id: LayerId, pos: (u32, u32)) {
+        if let Some(l) = self.find_mut(id) {
+            l.pos = pos;
+            self.changed = true;
+        }
+    }
+
+    pub fn set_z(&mut self, id: LayerId, z: i32) {
+        if let Some(l) = self.find_mut(id) {
+            l.z = z;
+            self.sort();
+        }
+    }
+
+    pub fn set_visible(&mut self, id: LayerId, visible: bool) {
+        if let Some(l) = self.find_mut(id) {
+            l.visible = visible;
+            self.changed = true;
+        }
+    }
+
+    fn find_mut(&mut self, id: LayerId) -> Option<&mut Layer> {
+        self.layers.iter_mut().find(|l| l.id == id)
+    }
+
+    fn sort(&mut self) {
+        self.layers.sort_by_key(|l| (l.z, l.id.0));
+        self.changed = true;
+    }
+
+    /// Combine the visible layers into rows of cells.
+    fn compose(&self) -> Vec<Vec<Cell>> {
+        let (width, height) = (self.size.0 as usize, self.size.1 as usize);
+        let mut rows = vec![vec![Cell::blank(Style::default()); width]; height];
+        for layer in self.layers.iter().filter(|l| l.visible) {
+            let (lx, ly) = (layer.pos.0 as usize, layer.pos.1 as usize);
+            for (y, line) in layer.rect.lines().iter().enumerate() {
+                let row = match rows.get_mut(ly + y) {
+                    Some(row) => row,
+                    None => break,
+                };
+                for (x, cell) in line.cells().into_iter().enumerate() {
+                    if lx + x >= width {
+                        break;
+                    }
+                    if !cell.transparent {
+                        row[lx + x] = cell;
+                    }
+                }
+            }
+        }
+        // Halves of double width chars that lost their other half to a layer
+        // above become spaces.
+        for row in &mut rows {
+            for x in 0..width {
+                let broken = if row[x].is_continuation() {
+                    x == 0 || !row[x - 1].is_wide()
+                } else {
+                    row[x].is_wide() && (x + 1 == width || !row[x + 1].is_continuation())
+                };
+                if broken {
+                    row[x] = Cell {
+                        tag: row[x].tag,
+                        ..Cell::blank(row[x].style)
+                    };
+                }
+            }
+        }
+        rows
+    }
+
+    /// The visible cursor of the top layer that has one.
+    fn cursor(&self) -> Option<Cursor> {
+        self.layers
+            .iter()
+            .rev()
+            .filter(|l| l.visible && l.rect.cursor().visible)
+            .map(|l| l.rect.cursor().offset(l.pos))
+            .next()
+    }
+}
+
+/// Draw the cells in row from start to end, as one text per run of the same
+/// style and tag.
+fn draw_cells<R: RawPaintable>(
+    target: &mut R,
+    pos: (u32, u32),
+    row: &[Cell],
+    start: usize,
+    end: usize,
+) {
+    let mut x = start;
+    while x < end {
+        let (style, tag) = (row[x].style, row[x].tag);
+        let run = row[x..end]
+            .iter()
+            .take_while(|c| c.style == style && c.tag == tag)
+            .count();
+        let text = row[x..x + run].iter().map(|c| &c.text as &str).collect();
+        let mut text = StyledText::new(style, text);
+        text.tag = tag;
+        target.draw_text_at((pos.0 + x as u32, pos.1), &text);
+        x += run;
+    }
+}
+
+impl HasSize for Compositor {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+}
+
+impl PaintableWidget for Compositor {
+    fn draw_into<R: RawPaintable>(&self, target: &mut R, pos: (u32, u32)) {
+        for (y, row) in self.compose().iter().enumerate() {
+            draw_cells(target, (pos.0, pos.1 + y as u32), row, 0, row.len());
+        }
+        if let Some(cursor) = self.cursor() {
+            target.set_cursor(cursor.offset(pos));
+        }
+    }
+
+    fn draw_delta_into<R: RawPaintable>(&mut self, target: &mut R, pos: (u32, u32)) {
+        if !self.changed
+            && self.shown.is_some()
+            && !self.layers.iter().any(|l| l.rect.has_changes())
+        {
+            return;
+        }
+        let rows = self.compose();
+        for (y, row) in rows.iter().enumerate() {
+            let old = self.shown.as_ref().map(|s| &s[y]);
+            let pos = (pos.0, pos.1 + y as u32);
+            let same = |i: usize| match old {
+                Some(old) => old[i] == row[i],
+                None => false,
+            };
+            let mut x = 0;
+            while x < row.len() {
+                if same(x) {
+                    x += 1;
+                    continue;
+                }
+                // Never start drawing on the right half of a wide char.
+                let start = if row[x].is_continuation() { x - 1 } else { x };
+                let mut end = x + 1;
+                while end < row.len() && (row[end].is_continuation() || !same(end)) {
+                    end += 1;
+                }
+                draw_cells(target, pos, row, start, end);
+                x = end;
+            }
+        }
+        self.shown = Some(rows);
+
+        let cursor = self.cursor();
+        if cursor != self.shown_cursor {
+            target.set_cursor(cursor.unwrap_or_default().offset(pos));
+            self.shown_cursor = cursor;
+        }
+        self.mark_none_changed();
+    }
+
+    fn mark_all_changed(&mut self) {
+        self.shown = None;
+        self.shown_cursor = None;
+    }
+
+    fn mark_none_changed(&mut self) {
+        self.changed = false;
+        for l in &mut self.layers {
+            l.rect.mark_none_changed();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::Color;
+    use styledtext::Tag;
+    use testing::render_snapshot;
+
+    fn text(s: &str) -> StyledText {
+        StyledText::new(Style::default(), s.to_string())
+    }
+
+    #[test]
+    fn layers() {
+        let mut c = Compositor::new((8, 2));
+        let mut base = TermRect::new((8, 2));
+        base.draw_text_at((0, 0), &text("台北abcd"));
+        base.draw_text_at((0, 1), &text("12345678"));
+        let base = c.add_layer(base, (0, 0), 0);
+
+        // a popup with a transparent hole in the middle
+        let mut popup = TermRect::new_transparent((4, 2));
+        popup.draw_text_at((0, 0), &text("x"));
+        popup.draw_text_at((3, 0), &text("y"));
+        popup.draw_text_at((0, 1), &text("zz"));
+        let popup = c.add_layer(popup, (3, 0), 1);
+
+        let mut out = TermRect::new((8, 2));
+        c.draw_delta_into(&mut out, (0, 0));
+        assert_eq!(render_snapshot(&out), "8x2\n|台 xabyd|\n|123zz678|\n");
+
+        // only what changed is drawn
+        c.layer_mut(base).unwrap().draw_text_at((7, 1), &text("9"));
+        c.set_z(popup, -1);
+        let mut out = TermRect::new_transparent((8, 2));
+        c.draw_delta_into(&mut out, (0, 0));
+        assert_eq!(render_snapshot(&out), "8x2\n|  北  c |\n|   45  9|\n");
+
+        out.mark_none_changed();
+        c.draw_delta_into(&mut out, (0, 0));
+        assert!(!out.has_changes());
+    }
+
+    #[test]
+    fn style_changes() {
+        let mut c = Compositor::new((4, 1));
+        let l = c.add_layer(TermRect::new((4, 1)), (0, 0), 0);
+        let mut out = TermRect::new((4, 1));
+        c.draw_delta_into(&mut out, (0, 0));
+        out.mark_none_changed();
+
+        let red = Style::default().set_fg(Color::Indexed(1));
+        c.layer_mut(l)
+            .unwrap()
+            .draw_text_at((1, 0), &StyledText::new(red, "  ".to_string()));
+        c.draw_delta_into(&mut out, (0, 0));
+        assert_eq!(render_snapshot(&out), "4x1\n| [red]  [/] |\n");
+    }
+
+    #[test]
+    fn tags() {
+        let mut c = Compositor::new((6, 1));
+        let mut base = TermRect::new((6, 1));
+        base.draw_text_at((0, 0), &text("abcdef").with_tag(Tag(1)));
+        c.add_layer(base, (0, 0), 0);
+        let mut popup = TermRect::new_transparent((3, 1));
+        popup.draw_text_at((0, 0), &text("x").with_tag(Tag(2)));
+        popup.draw_text_at((1, 0), &text("y"));
+        let popup = c.add_layer(popup, (2, 0), 1);
+
+        let mut out = TermRect::new((6, 1));
+        c.draw_delta_into(&mut out, (0, 0));
+        let tags: Vec<_> = (0..6).map(|x| out.tag_at((x, 0))).collect();
+        assert_eq!(
+            tags,
+            vec![
+                Some(Tag(1)),
+                Some(Tag(1)),
+                Some(Tag(2)),
+                None,
+                Some(Tag(1)),
+                Some(Tag(1))
+            ]
+        );
+
+        // changing a layer that isn't there changes nothing
+        c.remove_layer(popup);
+        c.draw_delta_into(&mut out, (0, 0));
+        out.mark_none_changed();
+        c.set_position(popup, (0, 0));
+        c.set_visible(popup, false);
+        c.set_z(popup, 2);
+        assert!(!c.changed);
+    }
+}
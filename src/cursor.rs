@@ -0,0 +1,52 @@
+/// The shape the terminal draws its cursor in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+/// Cursor describes the terminal's own cursor, for instance to show where
+/// typed text will go in a text input.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub pos: (u32, u32),
+    pub visible: bool,
+    pub shape: CursorShape,
+    pub blinking: bool,
+}
+
+impl Cursor {
+    /// A visible blinking block cursor at pos.
+    pub fn at(pos: (u32, u32)) -> Cursor {
+        Cursor {
+            pos,
+            visible: true,
+            ..Cursor::default()
+        }
+    }
+
+    /// The same cursor, moved by offset. The position stops at u32::MAX
+    /// rather than overflowing.
+    pub fn offset(&self, offset: (u32, u32)) -> Cursor {
+        Cursor {
+            pos: (
+                self.pos.0.saturating_add(offset.0),
+                self.pos.1.saturating_add(offset.1),
+            ),
+            ..*self
+        }
+    }
+}
+
+impl Default for Cursor {
+    /// A hidden blinking block cursor at the origin.
+    fn default() -> Cursor {
+        Cursor {
+            pos: (0, 0),
+            visible: false,
+            shape: CursorShape::Block,
+            blinking: true,
+        }
+    }
+}
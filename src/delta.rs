@@ -29,7 +29,6 @@ impl Delta {
     /// Pass the same range and the length of the replace_width.
     pub fn add_splice_range<R: Borrow<Range<usize>>>(&mut self, r: R, len: usize) {
         let r = r.borrow();
-        let len = len as usize;
         *self = match *self {
             Unchanged => Range(r.start, r.start + len),
             Range(a, b) => {
@@ -0,0 +1,182 @@
+//! Checks of Line's invariants, for the proptest suite and the cargo fuzz
+//! target in fuzz/. Built for tests, or with the "fuzzing" feature.
+//!
+//! check_line_ops runs draws on a Line and on a naive model, a Vec of
+//! cells, and panics if the Line's width changes, its cells differ from the
+//! model's, or its delta misses a text whose cells changed.
+
+use line::{Cell, Line};
+use style::{Color, Style};
+use styledtext::StyledText;
+use termrect::{HasSize, PaintableWidget};
+
+use unicode_width::UnicodeWidthChar;
+
+/// The chars draws are made of, with double width ones to cut in half.
+pub const CHARS: &[char] = &['a', 'b', 'c', ' ', '台', '北'];
+
+/// LineOp is one step of a check.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LineOp {
+    /// Draw the text at x, with the style's fg set to Indexed(style). x can
+    /// be past the end of the line.
+    Draw { x: u32, text: String, style: u8 },
+    /// Mark the line as drawn, so later changes must be in its delta.
+    MarkNoneChanged,
+}
+
+/// Decode a line width and ops from fuzzer input. Any bytes decode to
+/// something.
+pub fn ops_from_bytes(data: &[u8]) -> (u32, Vec<LineOp>) {
+    let mut bytes = data.iter().cloned();
+    let width = bytes.next().map_or(0, |b| b as u32 % 24);
+    let mut ops = Vec::new();
+    while let Some(b) = bytes.next() {
+        if b == 0xff {
+            ops.push(LineOp::MarkNoneChanged);
+            continue;
+        }
+        let len = bytes.next().map_or(0, |b| b as usize % 8);
+        let text = bytes
+            .by_ref()
+            .take(len)
+            .map(|b| CHARS[b as usize % CHARS.len()])
+            .collect();
+        ops.push(LineOp::Draw {
+            x: b as u32 % (width + 4),
+            text,
+            style: (b >> 5) % 4,
+        });
+    }
+    (width, ops)
+}
+
+fn style_of(style: u8) -> Style {
+    Style::default().set_fg(Color::Indexed(style as u16))
+}
+
+/// Draw text at x on the model, the way a terminal would: a double width
+/// char that's half overwritten leaves a space in its style, and one that
+/// doesn't fit at the end becomes a space.
+fn model_draw(cells: &mut [Cell], x: u32, text: &str, style: Style) {
+    let width = cells.len();
+    let mut col = x as usize;
+    for c in text.chars() {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0);
+        if col >= width || w == 0 {
+            break;
+        }
+        if cells[col].is_continuation() {
+            cells[col - 1].text = " ".to_string();
+        }
+        if col + w > width {
+            for cell in &mut cells[col..] {
+                *cell = Cell::blank(style);
+            }
+            break;
+        }
+        if cells[col + w - 1].is_wide() {
+            cells[col + w].text = " ".to_string();
+        }
+        cells[col] = Cell {
+            style,
+            text: c.to_string(),
+            tag: None,
+            transparent: false,
+        };
+        if w > 1 {
+            cells[col + 1] = Cell {
+                style,
+                text: String::new(),
+                tag: None,
+                transparent: false,
+            };
+        }
+        col += w;
+    }
+}
+
+/// Run the ops on a Line of the width, panicking if an invariant breaks.
+pub fn check_line_ops(width: u32, ops: &[LineOp]) {
+    let mut line = Line::new(width);
+    let mut model = line.cells();
+    let mut drawn = model.clone();
+    for op in ops {
+        match *op {
+            LineOp::Draw { x, ref text, style } => {
+                let style = style_of(style);
+                line.draw_text_at(x, &StyledText::new(style, text.clone()));
+                model_draw(&mut model, x, text, style);
+            }
+            LineOp::MarkNoneChanged => {
+                line.mark_none_changed();
+                drawn = model.clone();
+                continue;
+            }
+        }
+
+        assert_eq!(line.size().0, width, "width changed after {:?}", op);
+        assert_eq!(line.cells(), model, "cells differ after {:?}", op);
+        let mut col = 0;
+        for (i, t) in line.texts.iter().enumerate() {
+            let span = col..col + t.width as usize;
+            assert!(
+                model[span.clone()] == drawn[span.clone()] || line.delta().contains(i),
+                "text {} at {:?} changed but isn't in the delta after {:?}",
+                i,
+                span,
+                op
+            );
+            col = span.end;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn op() -> impl Strategy<Value = LineOp> {
+        prop_oneof![
+            4 => (0u32..24, prop::collection::vec(prop::sample::select(CHARS), 0..8), 0u8..4)
+                .prop_map(|(x, chars, style)| LineOp::Draw {
+                    x,
+                    text: chars.into_iter().collect(),
+                    style,
+                }),
+            1 => Just(LineOp::MarkNoneChanged),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn line_draws(width in 0u32..20, ops in prop::collection::vec(op(), 0..20)) {
+            check_line_ops(width, &ops);
+        }
+    }
+
+    #[test]
+    fn cut_wide_chars() {
+        let draw = |x, text: &str| LineOp::Draw {
+            x,
+            text: text.to_string(),
+            style: 1,
+        };
+        check_line_ops(
+            6,
+            &[
+                draw(0, "台北台"),
+                LineOp::MarkNoneChanged,
+                draw(1, "a"),
+                draw(4, "b"),
+                draw(5, "台"),
+                draw(9, "a"),
+            ],
+        );
+        check_line_ops(
+            3,
+            &ops_from_bytes(b"\x03\x01\x02\x04\x05\xff\x02\x01\x00").1,
+        );
+    }
+}
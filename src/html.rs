@@ -0,0 +1,248 @@
+use palette::{hex, Palette};
+use style::{Color, Style, StyleAttr};
+use termrect::TermRect;
+
+/// HtmlOptions says how TermRect::to_html_with writes HTML.
+#[derive(Debug, Clone)]
+pub struct HtmlOptions {
+    /// The colors indexed and default colors are shown as.
+    pub palette: Palette,
+    /// Style spans with classes, using the rules from stylesheet, instead of
+    /// inline styles. Inverted text and RGB colors still get inline colors.
+    pub classes: bool,
+    /// The class of the <pre>, and the start of the other class names.
+    pub class_prefix: String,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> HtmlOptions {
+        HtmlOptions {
+            palette: Palette::default(),
+            classes: false,
+            class_prefix: "termrect".to_string(),
+        }
+    }
+}
+
+impl HtmlOptions {
+    /// CSS for the classes used when classes is set.
+    pub fn stylesheet(&self) -> String {
+        let p = &self.class_prefix;
+        let mut css = format!(
+            ".{}{{color:{};background:{}}}\n",
+            p,
+            hex(self.palette.fg),
+            hex(self.palette.bg)
+        );
+        for (i, c) in self.palette.colors.iter().enumerate() {
+            css += &format!(".{}-fg{}{{color:{}}}\n", p, i, hex(*c));
+            css += &format!(".{}-bg{}{{background:{}}}\n", p, i, hex(*c));
+        }
+        css += &format!(".{}-bold{{font-weight:bold}}\n", p);
+        css += &format!(".{}-italic{{font-style:italic}}\n", p);
+        css += &format!(".{}-faint{{opacity:0.5}}\n", p);
+        css += &format!(".{}-underline{{text-decoration:underline}}\n", p);
+        css += &format!(".{}-crossedout{{text-decoration:line-through}}\n", p);
+        css += &format!(
+            ".{0}-underline.{0}-crossedout{{text-decoration:underline line-through}}\n",
+            p
+        );
+        css
+    }
+
+    /// The class and style attributes for a span of text in the style.
+    fn span_attrs(&self, style: Style) -> (Vec<String>, Vec<String>) {
+        let mut classes = Vec::new();
+        let mut css = Vec::new();
+        let p = &self.class_prefix;
+        let inverted = style.isset(StyleAttr::Invert);
+        if self.classes && !inverted {
+            for &(c, name, prop) in &[
+                (style.fg(), "fg", "color"),
+                (style.bg(), "bg", "background"),
+            ] {
+                match c {
+                    Color::Indexed(i) if (i as usize) < self.palette.colors.len() => {
+                        classes.push(format!("{}-{}{}", p, name, i))
+                    }
+                    Color::RGB(r, g, b) => css.push(format!("{}:{}", prop, hex((r, g, b)))),
+                    _ => {}
+                }
+            }
+        } else {
+            // Faint is done with the colors here, rather than opacity.
+            let (fg, bg) = self.palette.resolve(if self.classes {
+                style.clear(StyleAttr::Faint)
+            } else {
+                style
+            });
+            if fg != self.palette.fg {
+                css.push(format!("color:{}", hex(fg)));
+            }
+            if bg != self.palette.bg {
+                css.push(format!("background:{}", hex(bg)));
+            }
+        }
+
+        let attrs = [
+            (StyleAttr::Bold, "font-weight:bold"),
+            (StyleAttr::Italic, "font-style:italic"),
+            (StyleAttr::Faint, ""),
+            (StyleAttr::Underline, ""),
+            (StyleAttr::CrossedOut, ""),
+        ];
+        for &(a, prop) in &attrs {
+            if !style.isset(a) {
+                continue;
+            }
+            if self.classes {
+                classes.push(format!("{}-{}", p, a.name()));
+            } else if !prop.is_empty() {
+                css.push(prop.to_string());
+            }
+        }
+        if !self.classes {
+            let lines: Vec<_> = [
+                (StyleAttr::Underline, "underline"),
+                (StyleAttr::CrossedOut, "line-through"),
+            ]
+            .iter()
+            .filter(|&&(a, _)| style.isset(a))
+            .map(|&(_, line)| line)
+            .collect();
+            if !lines.is_empty() {
+                css.push(format!("text-decoration:{}", lines.join(" ")));
+            }
+        }
+        (classes, css)
+    }
+}
+
+/// Escape text for HTML or XML. Control chars other than tab and newline
+/// aren't allowed in XML, and could carry escape sequences to whoever cats
+/// the file, so they become U+FFFD.
+pub(crate) fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            '\t' | '\n' => out.push(c),
+            c if c.is_control() => out.push('\u{fffd}'),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+impl TermRect {
+    /// The contents as a <pre> of HTML, with inline styles and xterm's
+    /// colors.
+    pub fn to_html(&self) -> String {
+        self.to_html_with(&HtmlOptions::default())
+    }
+
+    /// The contents as a <pre> of HTML, with a <span> for each run of text in
+    /// the same style.
+    pub fn to_html_with(&self, options: &HtmlOptions) -> String {
+        let mut out = if options.classes {
+            format!("<pre class=\"{}\">", escape_xml(&options.class_prefix))
+        } else {
+            format!(
+                "<pre style=\"color:{};background:{}\">",
+                hex(options.palette.fg),
+                hex(options.palette.bg)
+            )
+        };
+        for (y, line) in self.lines().iter().enumerate() {
+            if y > 0 {
+                out.push('\n');
+            }
+            for (_, style, text) in line.runs() {
+                let (classes, css) = options.span_attrs(style);
+                if classes.is_empty() && css.is_empty() {
+                    out += &escape_xml(&text);
+                    continue;
+                }
+                out += "<span";
+                if !classes.is_empty() {
+                    out += &format!(" class=\"{}\"", escape_xml(&classes.join(" ")));
+                }
+                if !css.is_empty() {
+                    out += &format!(" style=\"{}\"", css.join(";"));
+                }
+                out += &format!(">{}</span>", escape_xml(&text));
+            }
+        }
+        out += "</pre>";
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use styledtext::StyledText;
+    use termrect::RawPaintable;
+
+    fn sample() -> TermRect {
+        let mut tr = TermRect::new((8, 2));
+        let red = Style::default()
+            .set_fg(Color::Indexed(1))
+            .set(StyleAttr::Bold);
+        tr.draw_text_at((0, 0), &StyledText::new(red, "<a&b>".to_string()));
+        let inv = Style::default()
+            .set(StyleAttr::Invert)
+            .set(StyleAttr::Underline)
+            .set(StyleAttr::CrossedOut);
+        tr.draw_text_at((1, 1), &StyledText::new(inv, "x".to_string()));
+        tr
+    }
+
+    #[test]
+    fn inline() {
+        assert_eq!(
+            sample().to_html(),
+            "<pre style=\"color:#e5e5e5;background:#000000\">\
+             <span style=\"color:#cd0000;font-weight:bold\">&lt;a&amp;b&gt;</span>   \n \
+             <span style=\"color:#000000;background:#e5e5e5;\
+             text-decoration:underline line-through\">x</span>      </pre>"
+        );
+    }
+
+    #[test]
+    fn classes() {
+        let options = HtmlOptions {
+            classes: true,
+            class_prefix: "t".to_string(),
+            ..HtmlOptions::default()
+        };
+        assert_eq!(
+            sample().to_html_with(&options),
+            "<pre class=\"t\">\
+             <span class=\"t-fg1 t-bold\">&lt;a&amp;b&gt;</span>   \n \
+             <span class=\"t-underline t-crossedout\" \
+             style=\"color:#000000;background:#e5e5e5\">x</span>      </pre>"
+        );
+        let css = options.stylesheet();
+        assert!(css.contains(".t-fg196{color:#ff0000}\n"));
+        assert!(css.starts_with(".t{color:#e5e5e5;background:#000000}\n"));
+    }
+
+    #[test]
+    fn control_chars() {
+        assert_eq!(
+            escape_xml("a\x1b[2J\tb\n\x07\u{9b}"),
+            "a\u{fffd}[2J\tb\n\u{fffd}\u{fffd}"
+        );
+        let mut tr = TermRect::new((4, 1));
+        tr.draw_text_at(
+            (0, 0),
+            &StyledText::new(Style::default(), "a\x1bb".to_string()),
+        );
+        assert!(tr.to_html().contains("a\u{fffd}b"));
+    }
+}
@@ -0,0 +1,649 @@
+//! Decoding of the bytes a terminal sends as input: keys, mouse, pasted text
+//! and focus changes.
+//!
+//! Most of these have to be turned on first by writing the matching ENABLE_*
+//! sequence to the terminal.
+
+use std::char;
+use std::str;
+
+/// Report mouse presses, releases, drags and movement in SGR format.
+pub const ENABLE_MOUSE: &str = "\x1b[?1003h\x1b[?1006h";
+pub const DISABLE_MOUSE: &str = "\x1b[?1006l\x1b[?1003l";
+/// Wrap pasted text so it arrives as one Event::Paste.
+pub const ENABLE_BRACKETED_PASTE: &str = "\x1b[?2004h";
+pub const DISABLE_BRACKETED_PASTE: &str = "\x1b[?2004l";
+/// Report when the terminal gains or loses focus.
+pub const ENABLE_FOCUS_EVENTS: &str = "\x1b[?1004h";
+pub const DISABLE_FOCUS_EVENTS: &str = "\x1b[?1004l";
+/// Turn on the kitty keyboard protocol, with unambiguous escape codes and
+/// key release and repeat events.
+pub const ENABLE_KITTY_KEYBOARD: &str = "\x1b[>3u";
+pub const DISABLE_KITTY_KEYBOARD: &str = "\x1b[<u";
+
+bitfield! {
+    /// The modifier keys held down during a key or mouse event. The bits
+    /// are the same as the kitty keyboard protocol uses.
+    #[derive(Copy, Clone, PartialEq, Eq, Default)]
+    pub struct Modifiers(u8);
+    impl Debug;
+    pub shift, set_shift: 0;
+    pub alt, set_alt: 1;
+    pub ctrl, set_ctrl: 2;
+    pub super_, set_super: 3;
+    pub hyper, set_hyper: 4;
+    pub meta, set_meta: 5;
+    pub caps_lock, set_caps_lock: 6;
+    pub num_lock, set_num_lock: 7;
+}
+
+impl Modifiers {
+    /// Modifiers as sent in CSI parameters, which is one more than the bits.
+    fn from_param(p: u32) -> Modifiers {
+        Modifiers(p.saturating_sub(1) as u8)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyCode {
+    Char(char),
+    Enter,
+    Tab,
+    BackTab,
+    Backspace,
+    Esc,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    F(u8),
+    CapsLock,
+    ScrollLock,
+    NumLock,
+    PrintScreen,
+    Pause,
+    Menu,
+    /// A key code from the kitty keyboard protocol with no variant here.
+    Other(u32),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyEventKind {
+    Press,
+    Repeat,
+    /// Only sent with the kitty keyboard protocol.
+    Release,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub code: KeyCode,
+    pub modifiers: Modifiers,
+    pub kind: KeyEventKind,
+}
+
+impl KeyEvent {
+    /// A key press with no modifiers.
+    pub fn new(code: KeyCode) -> KeyEvent {
+        KeyEvent {
+            code,
+            modifiers: Modifiers::default(),
+            kind: KeyEventKind::Press,
+        }
+    }
+
+    fn with_modifiers(mut self, modifiers: Modifiers) -> KeyEvent {
+        self.modifiers = modifiers;
+        self
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    Back,
+    Forward,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MouseEventKind {
+    Down(MouseButton),
+    Up(MouseButton),
+    Drag(MouseButton),
+    Moved,
+    ScrollUp,
+    ScrollDown,
+    ScrollLeft,
+    ScrollRight,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct MouseEvent {
+    pub kind: MouseEventKind,
+    /// The cell, counted from (0, 0) like the positions given to
+    /// RawPaintable.
+    pub pos: (u32, u32),
+    pub modifiers: Modifiers,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    Key(KeyEvent),
+    Mouse(MouseEvent),
+    Paste(String),
+    FocusGained,
+    FocusLost,
+}
+
+/// The result of trying to parse one event from the front of the buffer.
+enum Parsed {
+    Event(Event, usize),
+    /// Bytes that are not understood, to be dropped.
+    Skip(usize),
+    Incomplete,
+}
+
+/// InputParser turns the bytes read from a terminal into Events. Bytes can be
+/// fed in however they arrive; partial sequences are kept until the rest
+/// comes.
+///
+/// A lone ESC byte can't be told apart from the start of an escape sequence
+/// until more bytes arrive, so if nothing more comes within a short time,
+/// call timeout to get it as the Esc key.
+#[derive(Debug, Default)]
+pub struct InputParser {
+    buf: Vec<u8>,
+}
+
+impl InputParser {
+    pub fn new() -> InputParser {
+        InputParser::default()
+    }
+
+    /// Add bytes read from the terminal.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Take the next complete event, or None if more bytes are needed.
+    pub fn next_event(&mut self) -> Option<Event> {
+        self.next(false)
+    }
+
+    /// Feed the bytes and return every complete event.
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.feed(bytes);
+        let mut events = Vec::new();
+        while let Some(e) = self.next_event() {
+            events.push(e);
+        }
+        events
+    }
+
+    /// Treat what is left over as complete, for when no more bytes arrived
+    /// within a short time. This is how the Esc key is recognized. A paste
+    /// that hasn't ended yet is kept, since a big one can arrive in chunks
+    /// with pauses between them.
+    pub fn timeout(&mut self) -> Vec<Event> {
+        let mut events = Vec::new();
+        // With complete set, only an unfinished paste is left in buf.
+        while let Some(e) = self.next(true) {
+            events.push(e);
+        }
+        events
+    }
+
+    fn next(&mut self, complete: bool) -> Option<Event> {
+        loop {
+            if self.buf.is_empty() {
+                return None;
+            }
+            match parse_event(&self.buf, complete) {
+                Parsed::Event(e, n) => {
+                    self.buf.drain(..n);
+                    return Some(e);
+                }
+                Parsed::Skip(n) => {
+                    self.buf.drain(..n);
+                }
+                Parsed::Incomplete => return None,
+            }
+        }
+    }
+}
+
+fn key(code: KeyCode, len: usize) -> Parsed {
+    Parsed::Event(Event::Key(KeyEvent::new(code)), len)
+}
+
+fn ctrl(c: char, len: usize) -> Parsed {
+    let mut m = Modifiers::default();
+    m.set_ctrl(true);
+    Parsed::Event(
+        Event::Key(KeyEvent::new(KeyCode::Char(c)).with_modifiers(m)),
+        len,
+    )
+}
+
+fn parse_event(buf: &[u8], complete: bool) -> Parsed {
+    if buf[0] != 0x1b {
+        return parse_plain(buf, complete);
+    }
+    match buf.get(1) {
+        None if complete => key(KeyCode::Esc, 1),
+        None => Parsed::Incomplete,
+        Some(b'[') => parse_csi(buf, complete),
+        Some(b'O') => parse_ss3(buf, complete),
+        Some(_) => {
+            // ESC before a key means alt was held.
+            let rest = if buf[1] == 0x1b {
+                key(KeyCode::Esc, 1)
+            } else {
+                parse_plain(&buf[1..], complete)
+            };
+            match rest {
+                Parsed::Event(Event::Key(mut k), n) => {
+                    k.modifiers.set_alt(true);
+                    Parsed::Event(Event::Key(k), n + 1)
+                }
+                Parsed::Incomplete => Parsed::Incomplete,
+                _ => Parsed::Skip(1),
+            }
+        }
+    }
+}
+
+/// Parse a key that isn't an escape sequence: a control char or UTF-8 char.
+fn parse_plain(buf: &[u8], complete: bool) -> Parsed {
+    match buf[0] {
+        b'\r' => key(KeyCode::Enter, 1),
+        b'\t' => key(KeyCode::Tab, 1),
+        0x7f | 0x08 => key(KeyCode::Backspace, 1),
+        0x1b => key(KeyCode::Esc, 1),
+        0x00 => ctrl(' ', 1),
+        b @ 0x01..=0x1a => ctrl((b'a' + b - 1) as char, 1),
+        b @ 0x1c..=0x1f => ctrl((b'4' + b - 0x1c) as char, 1),
+        b => {
+            let len = match b {
+                0x00..=0x7f => 1,
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Parsed::Skip(1),
+            };
+            if buf.len() < len {
+                return if complete {
+                    Parsed::Skip(buf.len())
+                } else {
+                    Parsed::Incomplete
+                };
+            }
+            match str::from_utf8(&buf[..len]) {
+                Ok(s) => key(KeyCode::Char(s.chars().next().unwrap()), len),
+                Err(_) => Parsed::Skip(1),
+            }
+        }
+    }
+}
+
+fn parse_ss3(buf: &[u8], complete: bool) -> Parsed {
+    let code = match buf.get(2) {
+        None if complete => return parse_event(&buf[..1], true),
+        None => return Parsed::Incomplete,
+        Some(b'A') => KeyCode::Up,
+        Some(b'B') => KeyCode::Down,
+        Some(b'C') => KeyCode::Right,
+        Some(b'D') => KeyCode::Left,
+        Some(b'H') => KeyCode::Home,
+        Some(b'F') => KeyCode::End,
+        Some(b'M') => KeyCode::Enter,
+        Some(&b @ b'P'..=b'S') => KeyCode::F(b - b'P' + 1),
+        Some(_) => return Parsed::Skip(3),
+    };
+    key(code, 3)
+}
+
+/// Split CSI parameters like "1;5:2" into [[1], [5, 2]]. Missing numbers are
+/// None.
+fn csi_params(params: &[u8]) -> Vec<Vec<Option<u32>>> {
+    if params.is_empty() {
+        return Vec::new();
+    }
+    params
+        .split(|&b| b == b';')
+        .map(|p| {
+            p.split(|&b| b == b':')
+                .map(|n| str::from_utf8(n).ok().and_then(|n| n.parse().ok()))
+                .collect()
+        })
+        .collect()
+}
+
+fn parse_csi(buf: &[u8], complete: bool) -> Parsed {
+    // parameter bytes, intermediate bytes, then a final byte
+    let end = match buf[2..].iter().position(|&b| (0x40..=0x7e).contains(&b)) {
+        Some(i) => i + 2,
+        None if complete => return Parsed::Skip(buf.len()),
+        None => return Parsed::Incomplete,
+    };
+    let len = end + 1;
+    let final_byte = buf[end];
+    let mut params = &buf[2..end];
+    let prefix = match params.first() {
+        Some(&b @ b'<'..=b'?') => {
+            params = &params[1..];
+            Some(b)
+        }
+        _ => None,
+    };
+
+    match (prefix, final_byte) {
+        (Some(b'<'), b'M') | (Some(b'<'), b'm') => {
+            return parse_sgr_mouse(&csi_params(params), final_byte == b'm', len)
+        }
+        (Some(_), _) => return Parsed::Skip(len),
+        (None, b'I') if params.is_empty() => return Parsed::Event(Event::FocusGained, len),
+        (None, b'O') if params.is_empty() => return Parsed::Event(Event::FocusLost, len),
+        _ => {}
+    }
+
+    let params = csi_params(params);
+    let param = |i: usize, j: usize| {
+        params
+            .get(i)
+            .and_then(|p| p.get(j).cloned())
+            .unwrap_or(None)
+    };
+    let modifiers = Modifiers::from_param(param(1, 0).unwrap_or(1));
+    let kind = match param(1, 1) {
+        Some(2) => KeyEventKind::Repeat,
+        Some(3) => KeyEventKind::Release,
+        _ => KeyEventKind::Press,
+    };
+
+    let code = match final_byte {
+        b'A' => KeyCode::Up,
+        b'B' => KeyCode::Down,
+        b'C' => KeyCode::Right,
+        b'D' => KeyCode::Left,
+        b'H' => KeyCode::Home,
+        b'F' => KeyCode::End,
+        b'E' => KeyCode::Other(57427), // keypad begin
+        b'P' => KeyCode::F(1),
+        b'Q' => KeyCode::F(2),
+        // CSI R is left out, since it's also a cursor position report.
+        b'S' => KeyCode::F(4),
+        b'Z' => KeyCode::BackTab,
+        b'~' => match param(0, 0) {
+            Some(200) => return parse_paste(buf, len),
+            Some(1) | Some(7) => KeyCode::Home,
+            Some(2) => KeyCode::Insert,
+            Some(3) => KeyCode::Delete,
+            Some(4) | Some(8) => KeyCode::End,
+            Some(5) => KeyCode::PageUp,
+            Some(6) => KeyCode::PageDown,
+            Some(n @ 11..=15) => KeyCode::F(n as u8 - 10),
+            Some(n @ 17..=21) => KeyCode::F(n as u8 - 11),
+            Some(n @ 23..=26) => KeyCode::F(n as u8 - 12),
+            Some(n @ 28..=29) => KeyCode::F(n as u8 - 13),
+            Some(n @ 31..=34) => KeyCode::F(n as u8 - 14),
+            _ => return Parsed::Skip(len),
+        },
+        b'u' => match param(0, 0) {
+            Some(n) => kitty_key_code(n),
+            None => return Parsed::Skip(len),
+        },
+        _ => return Parsed::Skip(len),
+    };
+    Parsed::Event(
+        Event::Key(KeyEvent {
+            code,
+            modifiers,
+            kind,
+        }),
+        len,
+    )
+}
+
+/// Map a kitty keyboard protocol key code to a KeyCode.
+fn kitty_key_code(n: u32) -> KeyCode {
+    match n {
+        9 => KeyCode::Tab,
+        13 => KeyCode::Enter,
+        27 => KeyCode::Esc,
+        127 => KeyCode::Backspace,
+        57358 => KeyCode::CapsLock,
+        57359 => KeyCode::ScrollLock,
+        57360 => KeyCode::NumLock,
+        57361 => KeyCode::PrintScreen,
+        57362 => KeyCode::Pause,
+        57363 => KeyCode::Menu,
+        57376..=57398 => KeyCode::F((n - 57376 + 13) as u8),
+        57344..=63743 => KeyCode::Other(n),
+        _ => char::from_u32(n).map_or(KeyCode::Other(n), KeyCode::Char),
+    }
+}
+
+fn parse_sgr_mouse(params: &[Vec<Option<u32>>], release: bool, len: usize) -> Parsed {
+    let param = |i: usize| {
+        params
+            .get(i)
+            .and_then(|p| p.first().cloned())
+            .unwrap_or(None)
+    };
+    let (b, x, y) = match (param(0), param(1), param(2)) {
+        (Some(b), Some(x), Some(y)) => (b, x, y),
+        _ => return Parsed::Skip(len),
+    };
+
+    let button = match (b & 0b1100_0011, b & 64 != 0) {
+        (_, true) => None,
+        (0, _) => Some(MouseButton::Left),
+        (1, _) => Some(MouseButton::Middle),
+        (2, _) => Some(MouseButton::Right),
+        (128, _) => Some(MouseButton::Back),
+        (129, _) => Some(MouseButton::Forward),
+        _ => None,
+    };
+    let kind = if b & 64 != 0 {
+        match b & 3 {
+            0 => MouseEventKind::ScrollUp,
+            1 => MouseEventKind::ScrollDown,
+            2 => MouseEventKind::ScrollLeft,
+            _ => MouseEventKind::ScrollRight,
+        }
+    } else {
+        match (button, b & 32 != 0, release) {
+            (Some(button), true, _) => MouseEventKind::Drag(button),
+            (None, true, _) => MouseEventKind::Moved,
+            (Some(button), false, false) => MouseEventKind::Down(button),
+            (Some(button), false, true) => MouseEventKind::Up(button),
+            (None, false, _) => return Parsed::Skip(len),
+        }
+    };
+
+    Parsed::Event(
+        Event::Mouse(MouseEvent {
+            kind,
+            pos: (x.saturating_sub(1), y.saturating_sub(1)),
+            // shift, alt and ctrl are bits 2, 3 and 4
+            modifiers: Modifiers(((b >> 2) & 0b111) as u8),
+        }),
+        len,
+    )
+}
+
+fn parse_paste(buf: &[u8], start: usize) -> Parsed {
+    const END: &[u8] = b"\x1b[201~";
+    match buf[start..].windows(END.len()).position(|w| w == END) {
+        Some(i) => {
+            let text = String::from_utf8_lossy(&buf[start..start + i]).into_owned();
+            Parsed::Event(Event::Paste(text), start + i + END.len())
+        }
+        None => Parsed::Incomplete,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn mods(shift: bool, alt: bool, ctrl: bool) -> Modifiers {
+        let mut m = Modifiers::default();
+        m.set_shift(shift);
+        m.set_alt(alt);
+        m.set_ctrl(ctrl);
+        m
+    }
+
+    fn key_event(code: KeyCode, modifiers: Modifiers) -> Event {
+        Event::Key(KeyEvent::new(code).with_modifiers(modifiers))
+    }
+
+    #[test]
+    fn plain_keys() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            p.parse("a台\r\x7f\x01".as_bytes()),
+            vec![
+                key_event(KeyCode::Char('a'), mods(false, false, false)),
+                key_event(KeyCode::Char('台'), mods(false, false, false)),
+                key_event(KeyCode::Enter, mods(false, false, false)),
+                key_event(KeyCode::Backspace, mods(false, false, false)),
+                key_event(KeyCode::Char('a'), mods(false, false, true)),
+            ]
+        );
+
+        // split UTF-8 waits for the rest
+        let bytes = "台".as_bytes();
+        assert_eq!(p.parse(&bytes[..1]), vec![]);
+        assert_eq!(
+            p.parse(&bytes[1..]),
+            vec![key_event(KeyCode::Char('台'), mods(false, false, false))]
+        );
+    }
+
+    #[test]
+    fn escape() {
+        let mut p = InputParser::new();
+        assert_eq!(p.parse(b"\x1b"), vec![]);
+        assert_eq!(
+            p.timeout(),
+            vec![key_event(KeyCode::Esc, mods(false, false, false))]
+        );
+
+        assert_eq!(
+            p.parse(b"\x1bx"),
+            vec![key_event(KeyCode::Char('x'), mods(false, true, false))]
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            p.parse(b"\x1b[A\x1bOP\x1b[1;5C\x1b[3~\x1b[15;2~\x1b[Z"),
+            vec![
+                key_event(KeyCode::Up, mods(false, false, false)),
+                key_event(KeyCode::F(1), mods(false, false, false)),
+                key_event(KeyCode::Right, mods(false, false, true)),
+                key_event(KeyCode::Delete, mods(false, false, false)),
+                key_event(KeyCode::F(5), mods(true, false, false)),
+                key_event(KeyCode::BackTab, mods(false, false, false)),
+            ]
+        );
+
+        // split sequences wait for the rest
+        assert_eq!(p.parse(b"\x1b[1;"), vec![]);
+        assert_eq!(
+            p.parse(b"3D"),
+            vec![key_event(KeyCode::Left, mods(false, true, false))]
+        );
+    }
+
+    #[test]
+    fn kitty_keyboard() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            p.parse(b"\x1b[97;5u\x1b[97;1:3u\x1b[27u\x1b[57376u"),
+            vec![
+                key_event(KeyCode::Char('a'), mods(false, false, true)),
+                Event::Key(KeyEvent {
+                    code: KeyCode::Char('a'),
+                    modifiers: Modifiers::default(),
+                    kind: KeyEventKind::Release,
+                }),
+                key_event(KeyCode::Esc, mods(false, false, false)),
+                key_event(KeyCode::F(13), mods(false, false, false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn mouse() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            p.parse(b"\x1b[<0;3;4M\x1b[<16;3;4m\x1b[<32;5;4M\x1b[<35;6;4M\x1b[<65;1;1M"),
+            vec![
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Down(MouseButton::Left),
+                    pos: (2, 3),
+                    modifiers: mods(false, false, false),
+                }),
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Up(MouseButton::Left),
+                    pos: (2, 3),
+                    modifiers: mods(false, false, true),
+                }),
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Drag(MouseButton::Left),
+                    pos: (4, 3),
+                    modifiers: mods(false, false, false),
+                }),
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::Moved,
+                    pos: (5, 3),
+                    modifiers: mods(false, false, false),
+                }),
+                Event::Mouse(MouseEvent {
+                    kind: MouseEventKind::ScrollDown,
+                    pos: (0, 0),
+                    modifiers: mods(false, false, false),
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn paste_and_focus() {
+        let mut p = InputParser::new();
+        assert_eq!(
+            p.parse(b"\x1b[I\x1b[200~a\x1b[Ab"),
+            vec![Event::FocusGained]
+        );
+        assert_eq!(
+            p.parse(b"c\x1b[201~\x1b[O"),
+            vec![Event::Paste("a\x1b[Abc".to_string()), Event::FocusLost]
+        );
+
+        // a pause in the middle of a paste doesn't lose it
+        assert_eq!(p.parse(b"\x1b[200~one\x1b"), vec![]);
+        assert_eq!(p.timeout(), vec![]);
+        assert_eq!(
+            p.parse(b" two\x1b[201~\x1b"),
+            vec![Event::Paste("one\x1b two".to_string())]
+        );
+        assert_eq!(
+            p.timeout(),
+            vec![key_event(KeyCode::Esc, mods(false, false, false))]
+        );
+    }
+}
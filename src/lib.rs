@@ -1,18 +1,59 @@
 #[macro_use]
 extern crate bitfield;
 extern crate itertools;
+extern crate unicode_linebreak;
 extern crate unicode_width;
 
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
+#[cfg(test)]
+extern crate proptest;
+
+pub mod asciicast;
+pub mod color;
+pub mod compositor;
+pub mod cursor;
 pub mod delta;
+#[cfg(any(test, feature = "fuzzing"))]
+pub mod fuzzing;
+pub mod html;
+pub mod input;
 pub mod line;
+pub mod markup;
+pub mod palette;
+pub mod paragraph;
+pub mod selection;
 pub mod style;
+pub mod styledline;
 pub mod styledtext;
+pub mod svg;
 pub mod termrect;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod theme;
+pub mod vt;
+
+#[cfg(feature = "serde")]
+mod serialize;
 
 #[cfg(feature = "termion")]
 extern crate termion;
 #[cfg(feature = "termion")]
+pub mod session;
+#[cfg(feature = "termion")]
 pub mod terminal;
 
-pub use style::{Color, Style, StyleAttr};
-pub use termrect::TermRect;
+pub use color::ParseColorError;
+pub use cursor::{Cursor, CursorShape};
+pub use html::HtmlOptions;
+pub use markup::{parse_markup, MarkupError};
+pub use palette::Palette;
+pub use selection::Selection;
+pub use style::{Color, Style, StyleAttr, StylePatch};
+pub use styledline::StyledLine;
+pub use styledtext::{ControlPolicy, EllipsisMode, StyledText, Tag, TextPolicy};
+pub use svg::FontMetrics;
+pub use termrect::{Rect, TermRect};
+pub use theme::{Theme, ThemedPaint};
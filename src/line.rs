@@ -1,35 +1,142 @@
 use delta::{Delta, Delta::*};
-use style::Style;
-use styledtext::StyledText;
+use style::{Style, StylePatch};
+use styledtext::{StyledText, Tag};
 use termrect::{HasSize, PaintableWidget, RawPaintable};
 
-use std::rc::Rc;
+use std::slice;
+use unicode_width::UnicodeWidthChar;
 
 #[derive(Debug, Clone)]
 pub(crate) struct Line {
     // There are no gaps between these.
-    texts: Vec<StyledText>,
+    pub(crate) texts: Vec<StyledText>,
 
     delta: Delta,
+
+    /// Whether the text on this line carries on to the next line, rather
+    /// than ending in a line break.
+    pub(crate) wrapped: bool,
+}
+
+/// Cell is one column of a Line.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Cell {
+    pub(crate) style: Style,
+    /// The char in the cell, with any zero width chars that follow it. Empty
+    /// for the cell covered by the right half of a double width char.
+    pub(crate) text: String,
+    pub(crate) tag: Option<Tag>,
+    pub(crate) transparent: bool,
+}
+
+impl Cell {
+    pub(crate) fn blank(style: Style) -> Cell {
+        Cell {
+            style,
+            text: " ".to_string(),
+            tag: None,
+            transparent: false,
+        }
+    }
+
+    /// Whether this is the left half of a double width char.
+    pub(crate) fn is_wide(&self) -> bool {
+        self.text
+            .chars()
+            .next()
+            .and_then(UnicodeWidthChar::width)
+            .unwrap_or(0)
+            > 1
+    }
+
+    /// Whether this is the right half of a double width char.
+    pub(crate) fn is_continuation(&self) -> bool {
+        self.text.is_empty()
+    }
 }
 
 impl Line {
     pub fn new(width: u32) -> Line {
+        Line::filled(StyledText::repeat(Style::default(), ' ', width))
+    }
+
+    /// A line made of one text.
+    pub(crate) fn filled(text: StyledText) -> Line {
         Line {
-            texts: vec![StyledText {
-                style: Style::default(),
-                text: Rc::new(" ".repeat(width as usize)),
-                width,
-            }],
+            texts: vec![text],
             // TODO: Should this be Range(0, 1) instead?
             delta: Unchanged,
+            wrapped: false,
         }
     }
+
+    #[cfg(any(test, feature = "fuzzing"))]
+    pub(crate) fn delta(&self) -> &Delta {
+        &self.delta
+    }
+
+    /// Split the line up into cells, one per column.
+    pub(crate) fn cells(&self) -> Vec<Cell> {
+        let mut cells: Vec<Cell> = Vec::with_capacity(self.size().0 as usize);
+        for t in &self.texts {
+            let start = cells.len();
+            let end = start + t.width as usize;
+            let cell = |text: String| Cell {
+                style: t.style,
+                text,
+                tag: t.tag,
+                transparent: t.transparent,
+            };
+            for c in t.text.chars() {
+                match UnicodeWidthChar::width(c).unwrap_or(0) {
+                    0 if cells.len() > start => cells.last_mut().unwrap().text.push(c),
+                    0 => {}
+                    w if cells.len() + w <= end => {
+                        cells.push(cell(c.to_string()));
+                        if w > 1 {
+                            cells.push(cell(String::new()));
+                        }
+                    }
+                    // A char cut off at the end of the text.
+                    _ => break,
+                }
+            }
+            // Keep the cells lined up with the width, even if the text
+            // doesn't fill it.
+            while cells.len() < end {
+                cells.push(cell(" ".to_string()));
+            }
+        }
+        cells
+    }
 }
 
 impl Line {
+    /// The text covering column x.
+    pub(crate) fn text_at(&self, x: u32) -> Option<&StyledText> {
+        let mut t_end = 0;
+        for t in &self.texts {
+            t_end += t.width;
+            if x < t_end {
+                return Some(t);
+            }
+        }
+        None
+    }
+
     pub(crate) fn draw_text_at(&mut self, x: u32, txt: &StyledText) -> bool {
-        let txt_end = x + txt.width;
+        self.draw_texts_at(x, slice::from_ref(txt))
+    }
+
+    /// Draw the texts one after another from x, replacing what was there in
+    /// one splice.
+    pub(crate) fn draw_texts_at(&mut self, x: u32, txts: &[StyledText]) -> bool {
+        let txt_end = x + txts.iter().map(|t| t.width).sum::<u32>();
+        // Nothing to draw, and slicing would split a double width char for
+        // nothing.
+        if txt_end == x {
+            return false;
+        }
 
         let mut t_column;
         let mut t_end = 0;
@@ -44,14 +151,14 @@ impl Line {
             if !start_found && t_end > x {
                 start_index = i;
                 if t_column < x {
-                    start_sliced = Some(t.slice(..(x as usize) - (t_column as usize)));
+                    start_sliced = Some(t.slice(..(x - t_column) as usize));
                 }
                 start_found = true;
             }
             if start_found && t_end >= txt_end {
                 end_index = i;
                 if txt_end < t_end {
-                    end_sliced = Some(t.slice((txt_end as usize) - (t_column as usize)..));
+                    end_sliced = Some(t.slice((txt_end - t_column) as usize..));
                 }
                 break;
             }
@@ -62,14 +169,21 @@ impl Line {
             return false;
         }
 
-        let text = if end_index == self.texts.len() - 1 && txt_end > t_end {
-            txt.slice(..(txt.width - (txt_end - t_end)) as usize)
-        } else {
-            txt.clone()
-        };
-
-        let repl = [start_sliced, Some(text), end_sliced];
-        let repl: Vec<_> = repl.iter().flatten().cloned().collect();
+        // chop off anything that extends past the end of the line
+        let mut room = txt_end.min(t_end) - x;
+        let mut repl: Vec<_> = start_sliced.into_iter().collect();
+        for t in txts {
+            if t.width <= room {
+                repl.push(t.clone());
+                room -= t.width;
+            } else {
+                if room > 0 {
+                    repl.push(t.slice(..room as usize));
+                }
+                break;
+            }
+        }
+        repl.extend(end_sliced);
 
         let r = start_index..end_index + 1;
         self.delta.add_splice_range(r.clone(), repl.len());
@@ -77,6 +191,96 @@ impl Line {
 
         true
     }
+
+    /// The text of the line as runs of the same style, with the column each
+    /// starts at. Transparent text shows as spaces in the default style.
+    pub(crate) fn runs(&self) -> Vec<(u32, Style, String)> {
+        let mut runs: Vec<(u32, Style, String)> = Vec::new();
+        let mut x = 0;
+        for t in &self.texts {
+            let (style, text) = if t.transparent {
+                (Style::default(), " ".repeat(t.width as usize))
+            } else {
+                (t.style, t.text.to_string())
+            };
+            match runs.last_mut() {
+                Some(run) if run.1 == style => run.2.push_str(&text),
+                _ => runs.push((x, style, text)),
+            }
+            x += t.width;
+        }
+        runs
+    }
+
+    /// Restyle the columns from x0 to x1 with the patch, keeping the text.
+    /// Return true if something changed.
+    pub(crate) fn apply_style(&mut self, x0: u32, x1: u32, patch: &StylePatch) -> bool {
+        let mut repl = Vec::new();
+        // Zero width texts after the last text in repl, kept if another
+        // comes after them.
+        let mut zero_width = Vec::new();
+        let mut first = None;
+        let mut last = 0;
+        let mut changed = false;
+        let mut t_end = 0;
+        for (i, t) in self.texts.iter().enumerate() {
+            let t_column = t_end;
+            t_end += t.width;
+            if t_end <= x0 {
+                continue;
+            }
+            if t_column >= x1 {
+                break;
+            }
+            if t.width == 0 {
+                zero_width.push(t.clone());
+                continue;
+            }
+            first = first.or(Some(i));
+            last = i;
+            repl.append(&mut zero_width);
+
+            let style = patch.apply(t.style);
+            if style == t.style {
+                repl.push(t.clone());
+                continue;
+            }
+            changed = true;
+            let mut a = x0.max(t_column) - t_column;
+            let mut b = x1.min(t_end) - t_column;
+            // Restyle a double width char cut by an edge whole.
+            let mut col = 0;
+            for c in t.text.chars() {
+                let w = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+                if col < a && a < col + w {
+                    a = col;
+                }
+                if col < b && b < col + w {
+                    b = col + w;
+                }
+                col += w;
+            }
+            if a > 0 {
+                repl.push(t.slice(..a as usize));
+            }
+            let mut mid = t.slice(a as usize..b as usize);
+            mid.style = style;
+            repl.push(mid);
+            if b < t.width {
+                repl.push(t.slice(b as usize..));
+            }
+        }
+
+        match first {
+            Some(first) if changed => {
+                let r = first..last + 1;
+                self.delta.add_splice_range(r.clone(), repl.len());
+                self.texts.splice(r, repl);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 impl HasSize for Line {
@@ -143,5 +347,91 @@ mod test {
 
         line.draw_text_at(12, &StyledText::new(Style::default(), "123".to_string()));
         assert_eq!(strings_of(&line), vec!["xxx", "      ", "1"]);
+
+        // several texts go in as one splice, and are cut off the same way
+        line.mark_none_changed();
+        let texts = [
+            StyledText::new(Style::default(), "ab".to_string()),
+            StyledText::new(Style::default(), "cd".to_string()),
+            StyledText::new(Style::default(), "ef".to_string()),
+        ];
+        assert!(line.draw_texts_at(5, &texts));
+        assert_eq!(strings_of(&line), vec!["xxx", "  ", "ab", "cd", "e"]);
+        assert_eq!(line.delta, Range(1, 5));
+    }
+
+    #[test]
+    fn cut_wide_chars() {
+        let mut line = Line::new(6);
+        line.draw_text_at(0, &StyledText::new(Style::default(), "台北台".to_string()));
+
+        // the halves left over become spaces, and nothing else moves
+        line.draw_text_at(1, &StyledText::new(Style::default(), "a".to_string()));
+        assert_eq!(strings_of(&line), vec![" ", "a", "北台"]);
+        line.draw_text_at(4, &StyledText::new(Style::default(), "b".to_string()));
+        assert_eq!(strings_of(&line), vec![" ", "a", "北", "b", " "]);
+
+        // a double width char that doesn't fit at the end
+        line.draw_text_at(5, &StyledText::new(Style::default(), "台".to_string()));
+        assert_eq!(strings_of(&line), vec![" ", "a", "北", "b", " "]);
+        assert_eq!(line.size().0, 6);
+
+        // drawing nothing changes nothing, even in the middle of a char
+        line.mark_none_changed();
+        assert!(!line.draw_text_at(3, &StyledText::new(Style::default(), String::new())));
+        assert_eq!(strings_of(&line), vec![" ", "a", "北", "b", " "]);
+        assert_eq!(line.delta, Unchanged);
+    }
+
+    #[test]
+    fn apply_style() {
+        use style::{Color, StyleAttr};
+
+        let mut line = Line::new(10);
+        let red = Style::default().set_fg(Color::Indexed(1));
+        line.draw_text_at(0, &StyledText::new(red, "abcdef".to_string()));
+        line.mark_none_changed();
+
+        let bold = StylePatch::new().set(StyleAttr::Bold);
+        assert!(line.apply_style(2, 8, &bold));
+        assert_eq!(strings_of(&line), vec!["ab", "cdef", "  ", "  "]);
+        let styles: Vec<_> = line.texts.iter().map(|t| t.style).collect();
+        assert_eq!(
+            styles,
+            vec![
+                red,
+                red.set(StyleAttr::Bold),
+                Style::default().set(StyleAttr::Bold),
+                Style::default(),
+            ]
+        );
+        assert_eq!(line.delta, Range(0, 4));
+
+        // nothing to change
+        line.mark_none_changed();
+        assert!(!line.apply_style(3, 5, &bold));
+        assert_eq!(line.delta, Unchanged);
+
+        // double width chars cut by either edge are restyled whole
+        let mut line = Line::new(8);
+        line.draw_text_at(0, &StyledText::new(red, "a台北台b".to_string()));
+        assert!(line.apply_style(2, 4, &bold));
+        assert_eq!(strings_of(&line), vec!["a", "台北", "台b"]);
+        assert_eq!(line.texts[1].style, red.set(StyleAttr::Bold));
+        assert_eq!(line.texts[2].style, red);
+
+        // zero width texts in the range are kept
+        let mut line = Line::new(3);
+        line.texts = vec![
+            StyledText::new(red, "a".to_string()),
+            StyledText::new(red, "\u{301}".to_string()),
+            StyledText::new(red, "b".to_string()),
+            StyledText::new(red, "\u{301}".to_string()),
+            StyledText::new(red, "c".to_string()),
+        ];
+        assert!(line.apply_style(0, 2, &bold));
+        assert_eq!(strings_of(&line), vec!["a", "\u{301}", "b", "\u{301}", "c"]);
+        assert_eq!(line.texts[2].style, red.set(StyleAttr::Bold));
+        assert_eq!(line.texts[3].style, red);
     }
 }
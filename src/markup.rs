@@ -0,0 +1,253 @@
+use style::{Color, Style, StyleAttr, StylePatch};
+use styledline::StyledLine;
+use styledtext::StyledText;
+
+use std::error::Error;
+use std::fmt;
+
+/// What was wrong with a piece of markup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MarkupErrorKind {
+    /// A [ with no ] to close the tag.
+    UnterminatedTag,
+    /// A tag with nothing in it.
+    EmptyTag,
+    /// A word in a tag that isn't an attribute or color.
+    UnknownWord(String),
+    /// "on" or "not" at the end of a tag.
+    MissingWord(String),
+    /// A closing tag with no tag open, or that doesn't match the open tag.
+    UnmatchedClose(String),
+}
+
+/// MarkupError says what was wrong with markup, and where.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MarkupError {
+    pub kind: MarkupErrorKind,
+    /// Where the tag starts in the markup, in bytes.
+    pub index: usize,
+}
+
+impl fmt::Display for MarkupErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MarkupErrorKind::UnterminatedTag => write!(f, "unterminated tag"),
+            MarkupErrorKind::EmptyTag => write!(f, "empty tag"),
+            MarkupErrorKind::UnknownWord(ref w) => write!(f, "unknown style {:?}", w),
+            MarkupErrorKind::MissingWord(ref w) => write!(f, "nothing after {:?}", w),
+            MarkupErrorKind::UnmatchedClose(ref t) => write!(f, "unmatched [/{}]", t),
+        }
+    }
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at byte {}", self.kind, self.index)
+    }
+}
+
+impl Error for MarkupError {}
+
+/// Parse a color as Color::from_str does, or a bare index up to 255.
+fn parse_color(word: &str) -> Option<Color> {
+    word.parse()
+        .ok()
+        .or_else(|| word.parse::<u8>().ok().map(|i| Color::Indexed(i as u16)))
+}
+
+/// Parse a style spec such as "bold red on blue" into a patch. Words are
+/// attribute names, "not" and an attribute to clear it, a color for the
+/// foreground, and "on" and a color for the background.
+pub(crate) fn parse_spec(spec: &str) -> Result<StylePatch, MarkupErrorKind> {
+    let mut patch = StylePatch::new();
+    let mut words = spec.split_whitespace();
+    let mut any = false;
+    while let Some(word) = words.next() {
+        any = true;
+        let missing = || MarkupErrorKind::MissingWord(word.to_string());
+        patch = match word {
+            "on" => {
+                let w = words.next().ok_or_else(missing)?;
+                let color =
+                    parse_color(w).ok_or_else(|| MarkupErrorKind::UnknownWord(w.to_string()))?;
+                patch.set_bg(color)
+            }
+            "not" => {
+                let w = words.next().ok_or_else(missing)?;
+                let attr = StyleAttr::from_name(w)
+                    .ok_or_else(|| MarkupErrorKind::UnknownWord(w.to_string()))?;
+                patch.clear(attr)
+            }
+            _ => match (StyleAttr::from_name(word), parse_color(word)) {
+                (Some(attr), _) => patch.set(attr),
+                (_, Some(color)) => patch.set_fg(color),
+                _ => return Err(MarkupErrorKind::UnknownWord(word.to_string())),
+            },
+        };
+    }
+    if !any {
+        return Err(MarkupErrorKind::EmptyTag);
+    }
+    Ok(patch)
+}
+
+/// The spec parse_spec reads back as the changes from the default style to
+/// style, such as "bold red on blue". Empty for the default style.
+#[cfg(any(test, feature = "testing"))]
+pub(crate) fn style_spec(style: Style) -> String {
+    let mut words: Vec<String> = StyleAttr::ALL
+        .iter()
+        .filter(|&&a| style.isset(a))
+        .map(|a| a.name().to_string())
+        .collect();
+    if style.fg() != Color::Default {
+        words.push(style.fg().to_string());
+    }
+    if style.bg() != Color::Default {
+        words.push(format!("on {}", style.bg()));
+    }
+    words.join(" ")
+}
+
+/// Parse markup into styled text. "[bold red]" starts a span styled on top
+/// of the enclosing one, and "[/]" ends the last span started ("[/bold red]"
+/// does the same, but checks it matches). "[[" is a literal "[". Spans left
+/// open at the end are fine.
+///
+/// For example "[bold red]Error:[/] file not found".
+pub fn parse_markup(markup: &str) -> Result<StyledLine, MarkupError> {
+    parse_markup_with(Style::default(), markup)
+}
+
+/// Like parse_markup, with text outside any tag in the base style.
+pub fn parse_markup_with(base: Style, markup: &str) -> Result<StyledLine, MarkupError> {
+    let mut line = StyledLine::new();
+    // The open tags, and the style of the text inside each.
+    let mut open: Vec<(&str, Style)> = Vec::new();
+    let mut text = String::new();
+    let mut rest = markup;
+    while let Some(i) = rest.find('[') {
+        text.push_str(&rest[..i]);
+        if rest[i + 1..].starts_with('[') {
+            text.push('[');
+            rest = &rest[i + 2..];
+            continue;
+        }
+        let index = markup.len() - rest.len() + i;
+        let error = |kind| MarkupError { kind, index };
+        let len = rest[i..]
+            .find(']')
+            .ok_or_else(|| error(MarkupErrorKind::UnterminatedTag))?;
+        let tag = &rest[i + 1..i + len];
+        rest = &rest[i + len + 1..];
+
+        let style = open.last().map_or(base, |o| o.1);
+        if !text.is_empty() {
+            line.push(StyledText::new(style, text.split_off(0)));
+        }
+        if let Some(close) = tag.strip_prefix('/') {
+            let close = close.trim();
+            match open.last() {
+                Some(&(t, _)) if close.is_empty() || t.trim() == close => {
+                    open.pop();
+                }
+                _ => return Err(error(MarkupErrorKind::UnmatchedClose(close.to_string()))),
+            }
+        } else {
+            let patch = parse_spec(tag).map_err(error)?;
+            open.push((tag, patch.apply(style)));
+        }
+    }
+    text.push_str(rest);
+    if !text.is_empty() {
+        line.push(StyledText::new(open.last().map_or(base, |o| o.1), text));
+    }
+    Ok(line)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn spans(markup: &str) -> Vec<(String, Style)> {
+        parse_markup(markup)
+            .unwrap()
+            .texts()
+            .iter()
+            .map(|t| (t.text.to_string(), t.style))
+            .collect()
+    }
+
+    #[test]
+    fn markup() {
+        let red_bold = Style::default()
+            .set_fg(Color::Indexed(1))
+            .set(StyleAttr::Bold);
+        assert_eq!(
+            spans("[bold red]Error:[/] file [[not] found"),
+            vec![
+                ("Error:".to_string(), red_bold),
+                (" file [not] found".to_string(), Style::default()),
+            ]
+        );
+
+        // nested tags build on the enclosing style
+        let on_blue = red_bold.set_bg(Color::RGB(0, 0, 255));
+        assert_eq!(
+            spans("[bold red]a[on #00f not italic]b[/on #00f not italic]c"),
+            vec![
+                ("a".to_string(), red_bold),
+                ("b".to_string(), on_blue),
+                ("c".to_string(), red_bold),
+            ]
+        );
+
+        assert_eq!(
+            spans("[bright_cyan]x[/][208 on #102030]y"),
+            vec![
+                ("x".to_string(), Style::default().set_fg(Color::Indexed(14))),
+                (
+                    "y".to_string(),
+                    Style::default()
+                        .set_fg(Color::Indexed(208))
+                        .set_bg(Color::RGB(0x10, 0x20, 0x30))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn specs() {
+        let style = Style::default()
+            .set(StyleAttr::Italic)
+            .set_fg(Color::Indexed(100))
+            .set_bg(Color::RGB(1, 2, 3));
+        let spec = style_spec(style);
+        assert_eq!(spec, "italic ansi(100) on #010203");
+        assert_eq!(parse_spec(&spec).unwrap().apply(Style::default()), style);
+        assert_eq!(style_spec(Style::default()), "");
+    }
+
+    #[test]
+    fn errors() {
+        let error = |markup: &str| parse_markup(markup).unwrap_err();
+        assert_eq!(
+            error("ok [bold blurple]x"),
+            MarkupError {
+                kind: MarkupErrorKind::UnknownWord("blurple".to_string()),
+                index: 3,
+            }
+        );
+        assert_eq!(error("[bold x").kind, MarkupErrorKind::UnterminatedTag);
+        assert_eq!(error("a[]").kind, MarkupErrorKind::EmptyTag);
+        assert_eq!(
+            error("[red on]x").kind,
+            MarkupErrorKind::MissingWord("on".to_string())
+        );
+        assert_eq!(
+            error("[bold]x[/red]").kind,
+            MarkupErrorKind::UnmatchedClose("red".to_string())
+        );
+        assert_eq!(error("x[/]").to_string(), "unmatched [/] at byte 1");
+    }
+}
@@ -0,0 +1,117 @@
+use style::{Color, Style, StyleAttr};
+
+/// Palette says what RGB color each Color is shown as, for output that isn't
+/// a terminal, such as HTML.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Palette {
+    /// The 256 indexed colors.
+    pub colors: [(u8, u8, u8); 256],
+    /// The default foreground color.
+    pub fg: (u8, u8, u8),
+    /// The default background color.
+    pub bg: (u8, u8, u8),
+}
+
+impl Palette {
+    /// xterm's colors, with light grey text on black.
+    pub fn xterm() -> Palette {
+        const ANSI: [(u8, u8, u8); 16] = [
+            (0x00, 0x00, 0x00),
+            (0xcd, 0x00, 0x00),
+            (0x00, 0xcd, 0x00),
+            (0xcd, 0xcd, 0x00),
+            (0x00, 0x00, 0xee),
+            (0xcd, 0x00, 0xcd),
+            (0x00, 0xcd, 0xcd),
+            (0xe5, 0xe5, 0xe5),
+            (0x7f, 0x7f, 0x7f),
+            (0xff, 0x00, 0x00),
+            (0x00, 0xff, 0x00),
+            (0xff, 0xff, 0x00),
+            (0x5c, 0x5c, 0xff),
+            (0xff, 0x00, 0xff),
+            (0x00, 0xff, 0xff),
+            (0xff, 0xff, 0xff),
+        ];
+        const LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+        let mut colors = [(0, 0, 0); 256];
+        colors[..16].copy_from_slice(&ANSI);
+        // the 6x6x6 color cube
+        for i in 0..216 {
+            colors[16 + i] = (LEVELS[i / 36], LEVELS[i / 6 % 6], LEVELS[i % 6]);
+        }
+        // and the grey ramp
+        for i in 0..24 {
+            let v = 8 + 10 * i as u8;
+            colors[232 + i] = (v, v, v);
+        }
+        Palette {
+            colors,
+            fg: ANSI[7],
+            bg: ANSI[0],
+        }
+    }
+
+    /// The RGB color for c, as a foreground color if fg is true, else as a
+    /// background color. Indexes past the end of the palette are shown as the
+    /// default.
+    pub fn rgb(&self, c: Color, fg: bool) -> (u8, u8, u8) {
+        let default = if fg { self.fg } else { self.bg };
+        match c {
+            Color::Default => default,
+            Color::Indexed(i) => self.colors.get(i as usize).cloned().unwrap_or(default),
+            Color::RGB(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// The foreground and background colors text in the style is shown in.
+    /// These are swapped for inverted text, and faint text has its
+    /// foreground halfway to the background.
+    pub fn resolve(&self, style: Style) -> ((u8, u8, u8), (u8, u8, u8)) {
+        let (mut fg, mut bg) = (self.rgb(style.fg(), true), self.rgb(style.bg(), false));
+        if style.isset(StyleAttr::Invert) {
+            std::mem::swap(&mut fg, &mut bg);
+        }
+        if style.isset(StyleAttr::Faint) {
+            let mid = |a: u8, b: u8| ((a as u16 + b as u16) / 2) as u8;
+            fg = (mid(fg.0, bg.0), mid(fg.1, bg.1), mid(fg.2, bg.2));
+        }
+        (fg, bg)
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::xterm()
+    }
+}
+
+/// The color as #rrggbb.
+pub(crate) fn hex(c: (u8, u8, u8)) -> String {
+    format!("#{:02x}{:02x}{:02x}", c.0, c.1, c.2)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolve() {
+        let p = Palette::xterm();
+        assert_eq!(p.colors[9], (0xff, 0, 0));
+        assert_eq!(p.colors[196], (0xff, 0, 0));
+        assert_eq!(p.colors[255], (0xee, 0xee, 0xee));
+
+        let style = Style::default().set_fg(Color::Indexed(1));
+        assert_eq!(p.resolve(style), ((0xcd, 0, 0), (0, 0, 0)));
+        assert_eq!(
+            p.resolve(style.set(StyleAttr::Invert)),
+            ((0, 0, 0), (0xcd, 0, 0))
+        );
+        assert_eq!(
+            p.resolve(style.set(StyleAttr::Faint)),
+            ((0x66, 0, 0), (0, 0, 0))
+        );
+        assert_eq!(p.rgb(Color::Indexed(300), false), p.bg);
+    }
+}
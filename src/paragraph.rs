@@ -0,0 +1,419 @@
+use styledline::StyledLine;
+use styledtext::{ControlPolicy, StyledText, TextPolicy};
+use termrect::RawPaintable;
+
+use std::ops::Range;
+use unicode_linebreak::{linebreaks, BreakOpportunity};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Alignment {
+    Left,
+    Right,
+    Center,
+    /// Stretch the spaces between words so lines fill the width, except for
+    /// the last line of the paragraph.
+    Justify,
+}
+
+/// Paragraph wraps styled text to fit a width. Lines are broken where the
+/// Unicode line breaking algorithm (UAX #14) allows, and at line breaks in
+/// the text.
+#[derive(Debug, Clone)]
+pub struct Paragraph {
+    spans: Vec<StyledText>,
+    alignment: Alignment,
+    indent: u32,
+    hanging_indent: u32,
+}
+
+impl From<StyledLine> for Paragraph {
+    fn from(line: StyledLine) -> Paragraph {
+        Paragraph::new(line.texts().to_vec())
+    }
+}
+
+/// A word and the spaces after it, as byte ranges into the joined text.
+#[derive(Debug, Clone)]
+struct Word {
+    text: Range<usize>,
+    width: u32,
+    spaces: Range<usize>,
+}
+
+/// A laid out line, before alignment.
+#[derive(Debug, Default)]
+struct LineWords {
+    words: Vec<Word>,
+    // Whether the line ends the paragraph or was ended by a line break.
+    last: bool,
+}
+
+impl LineWords {
+    /// Width including the spaces between words, but not after the last.
+    fn width(&self) -> u32 {
+        let spaces: usize = self.words.iter().map(|w| w.spaces.len()).sum();
+        let trailing = self.words.last().map_or(0, |w| w.spaces.len());
+        self.words.iter().map(|w| w.width).sum::<u32>() + (spaces - trailing) as u32
+    }
+}
+
+impl Paragraph {
+    /// A paragraph of the texts. Tabs are expanded with the default
+    /// TextPolicy, counting columns from the start of each line of the text
+    /// before it's wrapped. Other control chars, except line breaks, are left
+    /// out.
+    pub fn new(spans: Vec<StyledText>) -> Paragraph {
+        let mut column = 0;
+        let mut expanded = Vec::with_capacity(spans.len());
+        for t in spans {
+            let mut text = String::with_capacity(t.text.len());
+            for (i, piece) in t.text.split('\n').enumerate() {
+                if i > 0 {
+                    text.push('\n');
+                    column = 0;
+                }
+                let policy = TextPolicy {
+                    start_column: column,
+                    controls: ControlPolicy::Strip,
+                    ..TextPolicy::default()
+                };
+                let piece = StyledText::with_policy(t.style, piece, &policy)
+                    .expect("stripping control chars can't fail");
+                column += piece.width;
+                text += &piece.text;
+            }
+            expanded.push(t.with_text(text));
+        }
+        Paragraph {
+            spans: expanded,
+            alignment: Alignment::Left,
+            indent: 0,
+            hanging_indent: 0,
+        }
+    }
+
+    pub fn set_alignment(mut self, alignment: Alignment) -> Paragraph {
+        self.alignment = alignment;
+        self
+    }
+
+    /// Indent the first line.
+    pub fn set_indent(mut self, indent: u32) -> Paragraph {
+        self.indent = indent;
+        self
+    }
+
+    /// Indent every line after the first.
+    pub fn set_hanging_indent(mut self, indent: u32) -> Paragraph {
+        self.hanging_indent = indent;
+        self
+    }
+
+    fn indent_of(&self, row: usize) -> u32 {
+        if row == 0 {
+            self.indent
+        } else {
+            self.hanging_indent
+        }
+    }
+
+    /// Lay the text out in lines that fit in width. Each line is a list of
+    /// texts and the column to draw each at.
+    pub fn layout(&self, width: u32) -> Vec<Vec<(u32, StyledText)>> {
+        let text: String = self.spans.iter().map(|t| &t.text as &str).collect();
+        let lines = self.break_lines(&text, width);
+        lines
+            .iter()
+            .enumerate()
+            .map(|(row, line)| self.align(&text, line, row, width))
+            .collect()
+    }
+
+    /// Draw the paragraph at pos, fitting it into width. Returns the number
+    /// of lines drawn. Only the text is drawn, not the space around it. Lines
+    /// that were wrapped, rather than ended by a line break, are marked with
+    /// set_wrapped.
+    pub fn draw_into<R: RawPaintable>(&self, target: &mut R, pos: (u32, u32), width: u32) -> u32 {
+        let text: String = self.spans.iter().map(|t| &t.text as &str).collect();
+        let lines = self.break_lines(&text, width);
+        for (row, line) in lines.iter().enumerate() {
+            let y = pos.1 + row as u32;
+            for (x, t) in self.align(&text, line, row, width) {
+                target.draw_text_at((pos.0 + x, y), &t);
+            }
+            target.set_wrapped(y, !line.last && row + 1 < lines.len());
+        }
+        lines.len() as u32
+    }
+
+    /// The number of lines the paragraph takes up in width.
+    pub fn height(&self, width: u32) -> u32 {
+        let text: String = self.spans.iter().map(|t| &t.text as &str).collect();
+        self.break_lines(&text, width).len() as u32
+    }
+
+    fn available(&self, row: usize, width: u32) -> u32 {
+        width.saturating_sub(self.indent_of(row)).max(1)
+    }
+
+    fn break_lines(&self, text: &str, width: u32) -> Vec<LineWords> {
+        let mut lines = vec![LineWords::default()];
+        let mut line_width = 0;
+        let mut start = 0;
+        for (end, opportunity) in linebreaks(text) {
+            let segment = &text[start..end];
+            let content = segment.trim_end_matches(|c: char| c == ' ' || c.is_control());
+            let spaces = content.len()
+                ..content.len()
+                    + segment[content.len()..]
+                        .trim_end_matches(|c: char| c.is_control())
+                        .len();
+            let mut word = Word {
+                text: start..start + content.len(),
+                width: UnicodeWidthStr::width(content) as u32,
+                spaces: start + spaces.start..start + spaces.end,
+            };
+            start = end;
+
+            // The spaces after the previous word only count if something
+            // comes after them on the same line.
+            let gap = lines
+                .last()
+                .unwrap()
+                .words
+                .last()
+                .map_or(0, |w| w.spaces.len() as u32);
+            if line_width > 0
+                && line_width + gap + word.width > self.available(lines.len() - 1, width)
+            {
+                lines.push(LineWords::default());
+                line_width = 0;
+            }
+
+            // A word too long for a line of its own is broken anywhere.
+            while word.width > self.available(lines.len() - 1, width) {
+                let avail = self.available(lines.len() - 1, width);
+                let (len, w) = prefix_fitting(&text[word.text.clone()], avail);
+                let head = Word {
+                    text: word.text.start..word.text.start + len,
+                    width: w,
+                    spaces: word.text.start + len..word.text.start + len,
+                };
+                word.text.start += len;
+                word.width -= w;
+                lines.last_mut().unwrap().words.push(head);
+                lines.push(LineWords::default());
+                line_width = 0;
+            }
+
+            let line = lines.last_mut().unwrap();
+            if !line.words.is_empty() {
+                line_width += gap;
+            }
+            line_width += word.width;
+            line.words.push(word);
+
+            if opportunity == BreakOpportunity::Mandatory {
+                line.last = true;
+                if end < text.len() {
+                    lines.push(LineWords::default());
+                    line_width = 0;
+                }
+            }
+        }
+        lines
+    }
+
+    fn align(
+        &self,
+        text: &str,
+        line: &LineWords,
+        row: usize,
+        width: u32,
+    ) -> Vec<(u32, StyledText)> {
+        let avail = self.available(row, width);
+        let extra = avail.saturating_sub(line.width());
+        let mut x = self.indent_of(row)
+            + match self.alignment {
+                Alignment::Left | Alignment::Justify => 0,
+                Alignment::Right => extra,
+                Alignment::Center => extra / 2,
+            };
+        let gaps = line.words.len().saturating_sub(1) as u32;
+        let stretch = self.alignment == Alignment::Justify && !line.last && gaps > 0;
+
+        let mut out = Vec::new();
+        for (i, word) in line.words.iter().enumerate() {
+            for t in self.styled(text, word.text.clone()) {
+                let w = t.width;
+                out.push((x, t));
+                x += w;
+            }
+            if i as u32 == gaps {
+                break;
+            }
+            let mut spaces = self.styled(text, word.spaces.clone());
+            if stretch {
+                // Spread the extra columns over the gaps, first ones first.
+                let n = extra / gaps + if (i as u32) < extra % gaps { 1 } else { 0 };
+                let extra_spaces = spaces
+                    .last()
+                    .or_else(|| out.last().map(|o| &o.1))
+                    .map(|t| t.with_text(" ".repeat(n as usize)));
+                spaces.extend(extra_spaces);
+            }
+            for t in spaces {
+                let w = t.width;
+                out.push((x, t));
+                x += w;
+            }
+        }
+        // A double width char on a line one column wide, or an indent wider
+        // than the line, can still go past the edge, so clip it there.
+        out.into_iter()
+            .filter(|&(x, _)| x < width)
+            .map(|(x, t)| {
+                if x + t.width > width {
+                    (x, t.slice(..(width - x) as usize))
+                } else {
+                    (x, t)
+                }
+            })
+            .collect()
+    }
+
+    /// The texts making up a byte range of the joined text.
+    fn styled(&self, text: &str, range: Range<usize>) -> Vec<StyledText> {
+        let mut out = Vec::new();
+        let mut start = 0;
+        for t in &self.spans {
+            let end = start + t.text.len();
+            let (a, b) = (range.start.max(start), range.end.min(end));
+            if a < b {
+                out.push(t.with_text(text[a..b].to_string()));
+            }
+            start = end;
+        }
+        out
+    }
+}
+
+/// The length in bytes and the width of the longest prefix of text that fits
+/// in width. Always includes at least one char.
+fn prefix_fitting(text: &str, width: u32) -> (usize, u32) {
+    let mut w = 0;
+    for (i, c) in text.char_indices() {
+        let cw = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+        if w + cw > width && i > 0 {
+            return (i, w);
+        }
+        w += cw;
+    }
+    (text.len(), w)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use selection::Selection;
+    use style::{Style, StyleAttr};
+    use termrect::{Rect, TermRect};
+
+    fn render(p: &Paragraph, width: u32) -> String {
+        let mut tr = TermRect::new((width, 8));
+        let rows = p.draw_into(&mut tr, (0, 0), width);
+        (0..rows)
+            .map(|y| tr.extract_text(&Selection::Block(Rect::new((0, y), (width, 1)))))
+            .collect::<Vec<_>>()
+            .join("|")
+    }
+
+    fn para(s: &str) -> Paragraph {
+        Paragraph::new(vec![StyledText::new(Style::default(), s.to_string())])
+    }
+
+    #[test]
+    fn wrapping() {
+        let p = para("the quick brown fox jumps");
+        assert_eq!(render(&p, 10), "the quick|brown fox|jumps");
+        assert_eq!(render(&p, 4), "the|quic|k|brow|n|fox|jump|s");
+        assert_eq!(p.height(10), 3);
+
+        // line breaks in the text are kept
+        assert_eq!(render(&para("a b\nc d"), 10), "a b|c d");
+
+        // wide chars count as two columns, and CJK can break between chars
+        assert_eq!(render(&para("台北台北台北"), 5), "台北|台北|台北");
+
+        // tabs go to the next tab stop in the unwrapped text, so words are
+        // kept apart and broken between
+        assert_eq!(render(&para("a\tb"), 10), "a       b");
+        assert_eq!(render(&para("aa\tbb"), 3), "aa|bb");
+        let spans = vec![
+            StyledText::new(Style::default(), "ab\ncd".to_string()),
+            StyledText::new(Style::default(), "e\tf\x1b".to_string()),
+        ];
+        let direct = StyledText::with_policy(Style::default(), "cde\tf", &TextPolicy::default());
+        assert_eq!(render(&Paragraph::new(spans), 10), "ab|cde     f");
+        assert_eq!(&*direct.unwrap().text, "cde     f");
+
+        // nothing goes past the width, even a double width char
+        let p = para("台a");
+        assert_eq!(render(&p, 1), "|a");
+        for line in p.layout(1) {
+            assert!(line.iter().all(|(x, t)| x + t.width <= 1));
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let p = para("aa bb cc dd ee");
+        assert_eq!(
+            render(&p.clone().set_alignment(Alignment::Right), 9),
+            " aa bb cc|    dd ee"
+        );
+        assert_eq!(
+            render(&p.clone().set_alignment(Alignment::Center), 9),
+            "aa bb cc|  dd ee"
+        );
+        assert_eq!(
+            render(&p.clone().set_alignment(Alignment::Justify), 10),
+            "aa  bb  cc|dd ee"
+        );
+        assert_eq!(
+            render(&p.clone().set_indent(2).set_hanging_indent(4), 10),
+            "  aa bb cc|    dd ee"
+        );
+    }
+
+    #[test]
+    fn styles() {
+        let bold = Style::default().set(StyleAttr::Bold);
+        let p = Paragraph::new(vec![
+            StyledText::new(Style::default(), "plain bo".to_string()),
+            StyledText::new(bold, "ld text".to_string()),
+        ]);
+        let lines = p.layout(10);
+        let texts: Vec<Vec<_>> = lines
+            .iter()
+            .map(|l| {
+                l.iter()
+                    .map(|(x, t)| (*x, t.text.to_string(), t.style == bold))
+                    .collect()
+            })
+            .collect();
+        assert_eq!(
+            texts,
+            vec![
+                vec![
+                    (0, "plain".to_string(), false),
+                    (5, " ".to_string(), false),
+                    (6, "bo".to_string(), false),
+                    (8, "ld".to_string(), true),
+                ],
+                vec![(0, "text".to_string(), true)],
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use line::Line;
+use termrect::{HasSize, Rect, TermRect};
+
+use unicode_width::UnicodeWidthChar;
+
+/// Selection is a region of a TermRect to copy text out of.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Selection {
+    /// Everything from start to end, both included, in reading order, the
+    /// way a mouse selects text. start and end can be given either way
+    /// around.
+    Linear { start: (u32, u32), end: (u32, u32) },
+    /// The same columns from every row in the rect.
+    Block(Rect),
+}
+
+/// The text in columns x0 to x1 of the line. A double width char is
+/// included if either half of it is.
+fn line_text(line: &Line, x0: u32, x1: u32) -> String {
+    let cells = line.cells();
+    let x1 = (x1 as usize).min(cells.len());
+    let mut x0 = (x0 as usize).min(x1);
+    if x0 > 0 && x0 < x1 && cells[x0].is_continuation() {
+        x0 -= 1;
+    }
+    cells[x0..x1]
+        .iter()
+        .map(|c| if c.transparent { " " } else { &c.text as &str })
+        .collect()
+}
+
+impl TermRect {
+    /// Copy the text out of the selection. Trailing blanks are trimmed from
+    /// each row, and rows are joined with newlines, except that a Linear
+    /// selection joins a soft-wrapped row (see set_wrapped) to the next one.
+    /// A wrapped row that doesn't reach the edge was broken at a space, so
+    /// one space goes between them, unless it ends in a double width char,
+    /// as text in scripts without spaces is broken anywhere.
+    pub fn extract_text(&self, selection: &Selection) -> String {
+        let lines = self.lines();
+        let mut out = String::new();
+        match *selection {
+            Selection::Linear { start, end } => {
+                let (start, end) = if (start.1, start.0) <= (end.1, end.0) {
+                    (start, end)
+                } else {
+                    (end, start)
+                };
+                let last = end.1.min(lines.len() as u32);
+                for y in start.1..last + 1 {
+                    let line = match lines.get(y as usize) {
+                        Some(line) => line,
+                        None => break,
+                    };
+                    let x0 = if y == start.1 { start.0 } else { 0 };
+                    let x1 = if y == end.1 {
+                        end.0.saturating_add(1)
+                    } else {
+                        self.size().0
+                    };
+                    let text = line_text(line, x0, x1);
+                    if line.wrapped && y != end.1 {
+                        let trimmed = text.trim_end_matches(' ');
+                        out.push_str(trimmed);
+                        let wide = trimmed
+                            .chars()
+                            .last()
+                            .and_then(UnicodeWidthChar::width)
+                            .unwrap_or(0)
+                            > 1;
+                        if trimmed.len() < text.len() && !wide {
+                            out.push(' ');
+                        }
+                    } else {
+                        out.push_str(text.trim_end_matches(' '));
+                        if y != end.1 && (y + 1) < lines.len() as u32 {
+                            out.push('\n');
+                        }
+                    }
+                }
+            }
+            Selection::Block(rect) => {
+                let rect = rect.clip(self.size());
+                for y in rect.pos.1..rect.bottom() {
+                    if y != rect.pos.1 {
+                        out.push('\n');
+                    }
+                    let text = line_text(&lines[y as usize], rect.pos.0, rect.right());
+                    out.push_str(text.trim_end_matches(' '));
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use paragraph::Paragraph;
+    use style::Style;
+    use styledtext::StyledText;
+    use termrect::RawPaintable;
+
+    fn termrect(rows: &[&str]) -> TermRect {
+        let mut tr = TermRect::new((8, rows.len() as u32));
+        for (y, row) in rows.iter().enumerate() {
+            tr.draw_text_at(
+                (0, y as u32),
+                &StyledText::new(Style::default(), row.to_string()),
+            );
+        }
+        tr
+    }
+
+    #[test]
+    fn linear() {
+        let mut tr = termrect(&["hello", "ab 台北", "abcdefgh", "ijk"]);
+        let sel = |start, end| Selection::Linear { start, end };
+
+        assert_eq!(tr.extract_text(&sel((2, 0), (1, 2))), "llo\nab 台北\nab");
+        // either way around
+        assert_eq!(tr.extract_text(&sel((1, 2), (2, 0))), "llo\nab 台北\nab");
+        // starting or ending on either half of a wide char includes it
+        assert_eq!(tr.extract_text(&sel((6, 1), (6, 1))), "北");
+        assert_eq!(tr.extract_text(&sel((5, 1), (5, 1))), "北");
+
+        tr.set_wrapped(2, true);
+        assert_eq!(tr.extract_text(&sel((4, 2), (7, 3))), "efghijk");
+        assert_eq!(tr.extract_text(&sel((0, 3), (u32::MAX, 3))), "ijk");
+    }
+
+    #[test]
+    fn wrapped_paragraph() {
+        let mut tr = TermRect::new((8, 4));
+        let text = StyledText::new(Style::default(), "hello big world\nok".to_string());
+        Paragraph::new(vec![text]).draw_into(&mut tr, (0, 0), 8);
+        assert!(tr.is_wrapped(0) && tr.is_wrapped(1));
+        assert!(!tr.is_wrapped(2));
+        let all = Selection::Linear {
+            start: (0, 0),
+            end: (7, 3),
+        };
+        assert_eq!(tr.extract_text(&all), "hello big world\nok");
+    }
+
+    #[test]
+    fn block() {
+        let tr = termrect(&["hello", "ab 台北", "abcdefgh", "ijk"]);
+        assert_eq!(
+            tr.extract_text(&Selection::Block(Rect::new((2, 0), (4, 10)))),
+            "llo\n 台北\ncdef\nk"
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! serde support, behind the "serde" feature.
+//!
+//! Colors are written as strings that Color::from_str reads back, such as
+//! "red" or "#ff8000", and style attributes as a list of names. A TermRect is
+//! written as its size and, for each line, the list of styled spans on it.
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, SerializeSeq, Serializer};
+
+use style::{Color, Style, StyleAttr, StyleAttrs};
+use styledline::StyledLine;
+use styledtext::{StyledText, Tag};
+use termrect::{is_sane_size, HasSize, RawPaintable, TermRect};
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Color, D::Error> {
+        let s = String::deserialize(d)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl Serialize for StyleAttrs {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let set: Vec<_> = StyleAttr::ALL.iter().filter(|a| a.isset_in(self)).collect();
+        let mut seq = s.serialize_seq(Some(set.len()))?;
+        for a in set {
+            seq.serialize_element(a.name())?;
+        }
+        seq.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for StyleAttrs {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<StyleAttrs, D::Error> {
+        let mut attrs = StyleAttrs::default();
+        for name in Vec::<String>::deserialize(d)? {
+            let a = StyleAttr::from_name(&name)
+                .ok_or_else(|| de::Error::custom(format!("unknown style attribute {:?}", name)))?;
+            a.set_to_in(true, &mut attrs);
+        }
+        Ok(attrs)
+    }
+}
+
+fn is_false(b: &bool) -> bool {
+    !*b
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextRepr {
+    #[serde(default)]
+    style: Style,
+    text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tag: Option<Tag>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    transparent: bool,
+}
+
+impl Serialize for StyledText {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        TextRepr {
+            style: self.style,
+            text: self.text.to_string(),
+            tag: self.tag,
+            transparent: self.transparent,
+        }
+        .serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for StyledText {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<StyledText, D::Error> {
+        let repr = TextRepr::deserialize(d)?;
+        let mut text = StyledText::new(repr.style, repr.text);
+        text.tag = repr.tag;
+        text.transparent = repr.transparent;
+        Ok(text)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct TermRectRepr {
+    size: (u32, u32),
+    lines: Vec<Vec<StyledText>>,
+    /// The rows that are wrapped.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    wrapped: Vec<u32>,
+}
+
+impl Serialize for TermRect {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let lines = self.lines();
+        TermRectRepr {
+            size: self.size(),
+            lines: lines.iter().map(|l| l.texts.clone()).collect(),
+            wrapped: (0..lines.len() as u32)
+                .filter(|&y| lines[y as usize].wrapped)
+                .collect(),
+        }
+        .serialize(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for TermRect {
+    /// The spans of each line are drawn into a blank TermRect of the size, so
+    /// everything starts out changed.
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<TermRect, D::Error> {
+        let repr = TermRectRepr::deserialize(d)?;
+        if !is_sane_size(repr.size) {
+            return Err(de::Error::custom(format!(
+                "a TermRect of size {}x{} is too big",
+                repr.size.0, repr.size.1
+            )));
+        }
+        if repr.lines.len() > repr.size.1 as usize {
+            return Err(de::Error::custom(format!(
+                "{} lines in a TermRect of height {}",
+                repr.lines.len(),
+                repr.size.1
+            )));
+        }
+        let mut tr = TermRect::new(repr.size);
+        for (y, texts) in repr.lines.into_iter().enumerate() {
+            let line: StyledLine = texts.into();
+            if line.width() > repr.size.0 {
+                return Err(de::Error::custom(format!(
+                    "line {} is {} wide in a TermRect of width {}",
+                    y,
+                    line.width(),
+                    repr.size.0
+                )));
+            }
+            tr.draw_rich_at((0, y as u32), &line);
+        }
+        for y in repr.wrapped {
+            tr.set_wrapped(y, true);
+        }
+        Ok(tr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate serde_json;
+
+    use super::*;
+    use termrect::PaintableWidget;
+
+    #[test]
+    fn styles() {
+        let style = Style::default()
+            .set_fg(Color::Indexed(9))
+            .set_bg(Color::RGB(0, 128, 255))
+            .set(StyleAttr::Bold)
+            .set(StyleAttr::Underline);
+        let json = serde_json::to_string(&style).unwrap();
+        assert_eq!(
+            json,
+            r##"{"fg":"bright_red","bg":"#0080ff","attrs":["bold","underline"]}"##
+        );
+        assert_eq!(serde_json::from_str::<Style>(&json).unwrap(), style);
+        assert_eq!(
+            serde_json::from_str::<Style>(r#"{"fg":"ansi(100)"}"#).unwrap(),
+            Style::default().set_fg(Color::Indexed(100))
+        );
+        assert!(serde_json::from_str::<Style>(r#"{"attrs":["blink"]}"#).is_err());
+    }
+
+    #[test]
+    fn termrect() {
+        let mut tr = TermRect::new((6, 2));
+        let bold = Style::default().set(StyleAttr::Bold);
+        tr.draw_text_at(
+            (1, 0),
+            &StyledText::new(bold, "ab".to_string()).with_tag(Tag(7)),
+        );
+        tr.draw_text_at((0, 1), &StyledText::transparent(2));
+        tr.set_wrapped(0, true);
+
+        let json = serde_json::to_string(&tr).unwrap();
+        let mut copy: TermRect = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&copy).unwrap(), json);
+        assert_eq!(copy.tag_at((2, 0)), Some(Tag(7)));
+        assert!(copy.is_wrapped(0));
+
+        // what was loaded gets drawn
+        let mut out = TermRect::new((6, 2));
+        copy.draw_delta_into(&mut out, (0, 0));
+        assert_eq!(out.tag_at((1, 0)), Some(Tag(7)));
+
+        assert!(
+            serde_json::from_str::<TermRect>(r#"{"size":[2,1],"lines":[[{"text":"abc"}]]}"#)
+                .is_err()
+        );
+        let err =
+            serde_json::from_str::<TermRect>(r#"{"size":[4294967295,4294967295],"lines":[]}"#)
+                .unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("a TermRect of size 4294967295x4294967295 is too big"));
+    }
+}
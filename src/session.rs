@@ -0,0 +1,145 @@
+use terminal::Terminal;
+
+use std::io::{self, Stdout, Write};
+use std::ops::{Deref, DerefMut};
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, Once};
+use termion;
+use termion::raw::{IntoRawMode, RawTerminal};
+
+// How many sessions are alive. The terminal is taken over by the first and
+// put back when the last is dropped, or by the panic hook, which sets this
+// to 0 so the sessions dropped while unwinding leave the terminal alone.
+static SESSIONS: AtomicUsize = AtomicUsize::new(0);
+// Restores the previous tty mode when taken out and dropped.
+static RAW: Mutex<Option<RawTerminal<Stdout>>> = Mutex::new(None);
+static INSTALL_HOOK: Once = Once::new();
+
+/// TerminalSession takes over the terminal on stdout: it switches to the
+/// alternate screen, hides the cursor and optionally puts the tty in raw mode.
+/// Everything is put back when the session is dropped, including while
+/// unwinding from a panic. It derefs to the Terminal to draw into.
+///
+/// Sessions can nest. They share the terminal, which is put back when the
+/// last one is dropped.
+pub struct TerminalSession {
+    terminal: Terminal<Stdout>,
+}
+
+impl TerminalSession {
+    /// Start a session. The size is taken from the tty. Fails if stdout is
+    /// not a tty.
+    pub fn new(raw_mode: bool) -> io::Result<TerminalSession> {
+        let mut out = io::stdout();
+        if !termion::is_tty(&out) {
+            // io::Error::other is too new for older toolchains.
+            #[allow(clippy::io_other_error)]
+            return Err(io::Error::new(io::ErrorKind::Other, "stdout is not a tty"));
+        }
+        let (w, h) = termion::terminal_size()?;
+        if raw_mode {
+            let mut raw = lock_raw();
+            if raw.is_none() {
+                *raw = Some(io::stdout().into_raw_mode()?);
+            }
+        }
+
+        install_panic_hook();
+        if SESSIONS.fetch_add(1, Ordering::SeqCst) == 0 {
+            write!(
+                out,
+                "{}{}{}",
+                termion::screen::ToAlternateScreen,
+                termion::cursor::Hide,
+                termion::clear::All
+            )?;
+        }
+
+        Ok(TerminalSession {
+            terminal: Terminal::new(out, (w as u32, h as u32)),
+        })
+    }
+
+    /// Read the size from the tty again, for instance after a SIGWINCH, and
+    /// resize the Terminal to match. Returns the new size.
+    pub fn update_size(&mut self) -> io::Result<(u32, u32)> {
+        let (w, h) = termion::terminal_size()?;
+        let size = (w as u32, h as u32);
+        self.terminal.resize(size);
+        Ok(size)
+    }
+}
+
+fn lock_raw() -> std::sync::MutexGuard<'static, Option<RawTerminal<Stdout>>> {
+    // A panic while holding the lock can't leave the Option half changed.
+    RAW.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Write out everything needed to leave the session: the style, the cursor
+/// shape and visibility, and the main screen.
+fn write_restore<W: Write>(w: &mut W) -> io::Result<()> {
+    write!(
+        w,
+        "{}\x1b[0 q{}{}",
+        termion::style::Reset,
+        termion::cursor::Show,
+        termion::screen::ToMainScreen
+    )?;
+    w.flush()
+}
+
+/// Put the terminal back, and the tty back in the mode it was in.
+fn restore() {
+    let _ = write_restore(&mut io::stdout());
+    lock_raw().take();
+}
+
+/// On panic, put the terminal back before the message is printed, so it's
+/// on the main screen and not mangled by raw mode.
+fn install_panic_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let prev = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if SESSIONS.swap(0, Ordering::SeqCst) > 0 {
+                restore();
+            }
+            prev(info);
+        }));
+    });
+}
+
+impl Deref for TerminalSession {
+    type Target = Terminal<Stdout>;
+    fn deref(&self) -> &Terminal<Stdout> {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalSession {
+    fn deref_mut(&mut self) -> &mut Terminal<Stdout> {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        let _ = self.terminal.end_frame();
+        let last = SESSIONS.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1));
+        if last == Ok(1) {
+            restore();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn restore_sequence() {
+        let mut out = Vec::new();
+        write_restore(&mut out).unwrap();
+        assert_eq!(out, b"\x1b[m\x1b[0 q\x1b[?25h\x1b[?1049l");
+    }
+}
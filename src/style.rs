@@ -23,7 +23,7 @@ impl Color {
 }
 
 bitfield! {
-    #[derive(Copy, Clone, PartialEq, Eq)]
+    #[derive(Copy, Clone, PartialEq, Eq, Default)]
     pub struct StyleAttrs(u16);
     impl Debug;
     bold, set_bold: 0;
@@ -45,7 +45,39 @@ pub enum StyleAttr {
 }
 
 impl StyleAttr {
-    fn isset_in(&self, attrs: &StyleAttrs) -> bool {
+    pub(crate) const ALL: [StyleAttr; 6] = [
+        StyleAttr::Bold,
+        StyleAttr::Italic,
+        StyleAttr::Faint,
+        StyleAttr::CrossedOut,
+        StyleAttr::Invert,
+        StyleAttr::Underline,
+    ];
+
+    /// The name used for the attribute in markup, themes and serialization.
+    pub fn name(&self) -> &'static str {
+        match self {
+            StyleAttr::Bold => "bold",
+            StyleAttr::Italic => "italic",
+            StyleAttr::Faint => "faint",
+            StyleAttr::CrossedOut => "crossedout",
+            StyleAttr::Invert => "invert",
+            StyleAttr::Underline => "underline",
+        }
+    }
+
+    /// The attribute with the name, or one of the aliases "dim", "strike"
+    /// and "reverse".
+    pub fn from_name(name: &str) -> Option<StyleAttr> {
+        match name {
+            "dim" => Some(StyleAttr::Faint),
+            "strike" => Some(StyleAttr::CrossedOut),
+            "reverse" => Some(StyleAttr::Invert),
+            _ => StyleAttr::ALL.iter().cloned().find(|a| a.name() == name),
+        }
+    }
+
+    pub(crate) fn isset_in(&self, attrs: &StyleAttrs) -> bool {
         match self {
             StyleAttr::Bold => attrs.bold(),
             StyleAttr::Italic => attrs.italic(),
@@ -56,7 +88,7 @@ impl StyleAttr {
         }
     }
 
-    fn set_to_in(&self, to: bool, attrs: &mut StyleAttrs) {
+    pub(crate) fn set_to_in(&self, to: bool, attrs: &mut StyleAttrs) {
         match self {
             StyleAttr::Bold => attrs.set_bold(to),
             StyleAttr::Italic => attrs.set_italic(to),
@@ -69,6 +101,7 @@ impl StyleAttr {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
 pub struct Style {
     fg: Color,
     bg: Color,
@@ -76,6 +109,14 @@ pub struct Style {
 }
 
 impl Style {
+    pub fn fg(&self) -> Color {
+        self.fg
+    }
+
+    pub fn bg(&self) -> Color {
+        self.bg
+    }
+
     pub fn set_fg(&self, c: Color) -> Style {
         Style {
             fg: c,
@@ -125,6 +166,16 @@ impl Style {
             attrs: newattrs,
         }
     }
+
+    /// Whether a space in this style looks the same as a cell erased with EL
+    /// or ECH.
+    #[cfg(feature = "termion")]
+    pub(crate) fn blank_is_erased(&self) -> bool {
+        self.bg == Color::Default
+            && !self.attrs.invert()
+            && !self.attrs.underline()
+            && !self.attrs.crossedout()
+    }
 }
 
 impl Default for Style {
@@ -137,6 +188,85 @@ impl Default for Style {
     }
 }
 
+/// StylePatch changes only some parts of a Style. Drawing over text with a
+/// patch, for instance to highlight a selection, keeps whatever colors and
+/// attributes the patch leaves alone.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct StylePatch {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    set: StyleAttrs,
+    clear: StyleAttrs,
+}
+
+impl StylePatch {
+    /// A patch that changes nothing.
+    pub fn new() -> StylePatch {
+        StylePatch::default()
+    }
+
+    pub fn set_fg(&self, c: Color) -> StylePatch {
+        StylePatch {
+            fg: Some(c),
+            ..*self
+        }
+    }
+
+    pub fn set_bg(&self, c: Color) -> StylePatch {
+        StylePatch {
+            bg: Some(c),
+            ..*self
+        }
+    }
+
+    /// Turn the attribute on. Undoes an earlier clear of the same attribute.
+    pub fn set(&self, a: StyleAttr) -> StylePatch {
+        let mut patch = *self;
+        a.set_to_in(true, &mut patch.set);
+        a.set_to_in(false, &mut patch.clear);
+        patch
+    }
+
+    /// Turn the attribute off. Undoes an earlier set of the same attribute.
+    pub fn clear(&self, a: StyleAttr) -> StylePatch {
+        let mut patch = *self;
+        a.set_to_in(false, &mut patch.set);
+        a.set_to_in(true, &mut patch.clear);
+        patch
+    }
+
+    /// Apply the patch to the style.
+    pub fn apply(&self, style: Style) -> Style {
+        Style {
+            fg: self.fg.unwrap_or(style.fg),
+            bg: self.bg.unwrap_or(style.bg),
+            attrs: StyleAttrs((style.attrs.0 | self.set.0) & !self.clear.0),
+        }
+    }
+
+    /// Combine two patches into one that does self, then other.
+    pub fn then(&self, other: &StylePatch) -> StylePatch {
+        StylePatch {
+            fg: other.fg.or(self.fg),
+            bg: other.bg.or(self.bg),
+            set: StyleAttrs((self.set.0 & !other.clear.0) | other.set.0),
+            clear: StyleAttrs((self.clear.0 & !other.set.0) | other.clear.0),
+        }
+    }
+}
+
+impl From<Style> for StylePatch {
+    /// A patch that replaces everything with the style.
+    fn from(style: Style) -> StylePatch {
+        StylePatch {
+            fg: Some(style.fg),
+            bg: Some(style.bg),
+            set: style.attrs,
+            clear: StyleAttrs(!style.attrs.0),
+        }
+    }
+}
+
 #[cfg(feature = "termion")]
 impl Display for Style {
     fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
@@ -0,0 +1,153 @@
+use styledtext::{column_range, truncate_texts, EllipsisMode, StyledText};
+use termrect::{HasSize, PaintableWidget, RawPaintable};
+
+use std::iter::FromIterator;
+use std::ops::RangeBounds;
+
+/// StyledLine is a run of StyledTexts in different styles, drawn one after
+/// the other on the same line.
+#[derive(Debug, Clone, Default)]
+pub struct StyledLine {
+    texts: Vec<StyledText>,
+    width: u32,
+}
+
+impl StyledLine {
+    pub fn new() -> StyledLine {
+        StyledLine::default()
+    }
+
+    pub fn texts(&self) -> &[StyledText] {
+        &self.texts
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    /// Add a text to the end. Empty texts are dropped.
+    pub fn push(&mut self, text: StyledText) {
+        if text.width > 0 || !text.text.is_empty() {
+            self.width += text.width;
+            self.texts.push(text);
+        }
+    }
+
+    /// Add the texts of other to the end.
+    pub fn append(&mut self, other: &StyledLine) {
+        self.extend(other.texts.iter().cloned());
+    }
+
+    /// This line followed by other.
+    pub fn concat(&self, other: &StyledLine) -> StyledLine {
+        let mut line = self.clone();
+        line.append(other);
+        line
+    }
+
+    /// Width sensitive slice across the texts. The range is in columns.
+    pub fn slice<R: RangeBounds<usize>>(&self, r: R) -> StyledLine {
+        let (a, b) = column_range(&r, self.width);
+        let mut line = StyledLine::new();
+        let mut start = 0;
+        for t in &self.texts {
+            let end = start + t.width as usize;
+            if a.max(start) < b.min(end) {
+                line.push(t.slice(a.max(start) - start..b.min(end) - start));
+            }
+            start = end;
+        }
+        line
+    }
+
+    /// Shorten the line to fit in width, marking where text was cut with an
+    /// ellipsis.
+    pub fn truncate(&self, width: u32, mode: EllipsisMode) -> StyledLine {
+        truncate_texts(&self.texts, width, mode)
+            .into_iter()
+            .collect()
+    }
+}
+
+impl From<StyledText> for StyledLine {
+    fn from(text: StyledText) -> StyledLine {
+        let mut line = StyledLine::new();
+        line.push(text);
+        line
+    }
+}
+
+impl From<Vec<StyledText>> for StyledLine {
+    fn from(texts: Vec<StyledText>) -> StyledLine {
+        texts.into_iter().collect()
+    }
+}
+
+impl FromIterator<StyledText> for StyledLine {
+    fn from_iter<I: IntoIterator<Item = StyledText>>(iter: I) -> StyledLine {
+        let mut line = StyledLine::new();
+        line.extend(iter);
+        line
+    }
+}
+
+impl Extend<StyledText> for StyledLine {
+    fn extend<I: IntoIterator<Item = StyledText>>(&mut self, iter: I) {
+        for t in iter {
+            self.push(t);
+        }
+    }
+}
+
+impl HasSize for StyledLine {
+    fn size(&self) -> (u32, u32) {
+        (self.width, 1)
+    }
+}
+
+impl PaintableWidget for StyledLine {
+    fn draw_into<R: RawPaintable>(&self, target: &mut R, pos: (u32, u32)) {
+        target.draw_rich_at(pos, self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::{Style, StyleAttr};
+    use termrect::TermRect;
+    use testing::render_snapshot;
+
+    fn snapshot_of(line: &StyledLine) -> String {
+        let mut tr = TermRect::new((line.width(), 1));
+        line.draw_into(&mut tr, (0, 0));
+        render_snapshot(&tr)
+    }
+
+    #[test]
+    fn building_and_slicing() {
+        let bold = Style::default().set(StyleAttr::Bold);
+        let line: StyledLine = vec![
+            StyledText::new(Style::default(), "ab".to_string()),
+            StyledText::new(Style::default(), "".to_string()),
+            StyledText::new(bold, "台北".to_string()),
+        ]
+        .into();
+        assert_eq!(line.texts().len(), 2);
+        assert_eq!(snapshot_of(&line), "6x1\n|ab[bold]台北[/]|\n");
+
+        let sliced = line.slice(1..4);
+        assert_eq!(snapshot_of(&sliced), "3x1\n|b[bold]台[/]|\n");
+        assert_eq!(snapshot_of(&line.slice(2..)), "4x1\n|[bold]台北[/]|\n");
+
+        let both = sliced.concat(&line.slice(..1));
+        assert_eq!(snapshot_of(&both), "4x1\n|b[bold]台[/]a|\n");
+
+        let short = line.truncate(5, EllipsisMode::End);
+        assert_eq!(snapshot_of(&short), "5x1\n|ab[bold]台…[/]|\n");
+    }
+}
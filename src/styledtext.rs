@@ -2,10 +2,19 @@ use style::Style;
 use termrect::{HasSize, PaintableWidget, RawPaintable};
 
 use std;
+use std::error::Error;
+use std::fmt;
 use std::ops::{Bound, RangeBounds};
 use std::rc::Rc;
+use std::slice;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
+/// Tag identifies whoever drew a span of text, so a cell can be traced back to
+/// it later, for instance to find which widget was clicked.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Tag(pub u32);
+
 /// StyledText represents a span of text that all has the same style. It also
 /// keeps track of the unicode width of the text.
 #[derive(Debug, Clone)]
@@ -13,38 +22,223 @@ pub struct StyledText {
     pub(crate) style: Style,
     pub(crate) text: Rc<String>,
     pub(crate) width: u32,
+    pub(crate) tag: Option<Tag>,
+    pub(crate) transparent: bool,
+}
+
+/// What to do with control characters, such as escape or newline. Terminals
+/// don't draw these, and may act on them, so untrusted text containing them
+/// can mess up the screen.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ControlPolicy {
+    /// Show them in caret notation, like ^[ for escape, the way cat -v does.
+    Replace,
+    /// Leave them out.
+    Strip,
+    /// Fail with a ControlCharError.
+    Reject,
+}
+
+/// TextPolicy says how StyledText::with_policy treats tabs and other control
+/// characters.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct TextPolicy {
+    /// Expand tabs to spaces, with a tab stop every tab_width columns. If
+    /// None, tabs are treated like any other control character.
+    pub tab_width: Option<u32>,
+    /// The column the text will be drawn at, so tab stops line up.
+    pub start_column: u32,
+    pub controls: ControlPolicy,
+}
+
+impl Default for TextPolicy {
+    /// Tab stops every 8 columns, and control characters replaced.
+    fn default() -> TextPolicy {
+        TextPolicy {
+            tab_width: Some(8),
+            start_column: 0,
+            controls: ControlPolicy::Replace,
+        }
+    }
+}
+
+/// A control character found in text by StyledText::with_policy with
+/// ControlPolicy::Reject.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ControlCharError {
+    pub ch: char,
+    /// Where it is in the text, in bytes.
+    pub index: usize,
+}
+
+impl fmt::Display for ControlCharError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "control character {:?} at byte {}", self.ch, self.index)
+    }
 }
 
-/// Width sensitive slice. A and B are counted in cells.
-fn width_slice(txt: &str, a: usize, b: usize) -> &str {
-    let mut width_so_far = 0;
-    let mut found_start = false;
-    let mut start_index = 0;
-    let mut end_index = txt.len();
+impl Error for ControlCharError {}
+
+/// Push the caret notation for a control char: ^@ to ^_ and ^? for C0 and
+/// DEL, and the same with M- in front for C1.
+fn push_caret(out: &mut String, c: char) {
+    let mut b = c as u32;
+    if b >= 0x80 {
+        out.push_str("M-");
+        b -= 0x80;
+    }
+    out.push('^');
+    out.push(((b as u8) ^ 0x40) as char);
+}
+
+/// Where to cut text out when truncating it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EllipsisMode {
+    /// Keep the end, like "…ing".
+    Start,
+    /// Keep both ends, like "/usr/…/file", for paths.
+    Middle,
+    /// Keep the start, like "Someth…".
+    End,
+}
+
+const ELLIPSIS: &str = "\u{2026}";
+
+/// The length in bytes and the width of the longest prefix of txt that fits
+/// in width, without splitting a double-width char.
+fn prefix_within(txt: &str, width: u32) -> (usize, u32) {
+    let mut w = 0;
     for (i, c) in txt.char_indices() {
-        if !found_start && width_so_far >= a {
-            //if width_so_far > a {
-            //panic!("Slice in the middle of a double-width char!");
-            //}
-            start_index = i;
-            found_start = true;
-        }
-        if found_start && width_so_far >= b {
-            end_index = i;
+        let cw = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+        if w + cw > width {
+            return (i, w);
+        }
+        w += cw;
+    }
+    (txt.len(), w)
+}
+
+/// The start in bytes and the width of the longest suffix of txt that fits in
+/// width, without splitting a double-width char.
+fn suffix_within(txt: &str, width: u32) -> (usize, u32) {
+    let mut w = 0;
+    let mut start = txt.len();
+    for (i, c) in txt.char_indices().rev() {
+        let cw = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+        if w + cw > width {
             break;
         }
+        w += cw;
+        start = i;
+    }
+    // Don't start with zero width chars whose base char was cut off.
+    let skip = txt[start..]
+        .chars()
+        .take_while(|&c| UnicodeWidthChar::width(c).unwrap_or(0) == 0)
+        .map(char::len_utf8)
+        .sum::<usize>();
+    (start + skip, w)
+}
+
+/// The first texts, up to width.
+fn texts_prefix(texts: &[StyledText], width: u32) -> (Vec<StyledText>, u32) {
+    let mut out = Vec::new();
+    let mut w = 0;
+    for t in texts {
+        if w + t.width <= width {
+            out.push(t.clone());
+            w += t.width;
+            continue;
+        }
+        let (len, pw) = prefix_within(&t.text, width - w);
+        if len > 0 {
+            out.push(t.with_text(t.text[..len].to_string()));
+            w += pw;
+        }
+        break;
+    }
+    (out, w)
+}
+
+/// The last texts, up to width.
+fn texts_suffix(texts: &[StyledText], width: u32) -> (Vec<StyledText>, u32) {
+    let mut out = Vec::new();
+    let mut w = 0;
+    for t in texts.iter().rev() {
+        if w + t.width <= width {
+            out.push(t.clone());
+            w += t.width;
+            continue;
+        }
+        let (start, sw) = suffix_within(&t.text, width - w);
+        if start < t.text.len() {
+            out.push(t.with_text(t.text[start..].to_string()));
+            w += sw;
+        }
+        break;
+    }
+    out.reverse();
+    (out, w)
+}
 
-        width_so_far += UnicodeWidthChar::width(c).unwrap_or(0);
+/// The text covering column x, or the last one if x is past the end.
+fn text_at_column(texts: &[StyledText], x: u32) -> &StyledText {
+    let mut end = 0;
+    for t in texts {
+        end += t.width;
+        if x < end {
+            return t;
+        }
     }
+    texts.last().unwrap()
+}
 
-    if !found_start {
-        return "";
+/// Truncate a sequence of texts to fit in width, like StyledText::truncate.
+/// The ellipsis takes the style of the text it replaces.
+pub fn truncate_texts(texts: &[StyledText], width: u32, mode: EllipsisMode) -> Vec<StyledText> {
+    let total: u32 = texts.iter().map(|t| t.width).sum();
+    if total <= width {
+        return texts.to_vec();
     }
+    if width == 0 {
+        return Vec::new();
+    }
+    let avail = width - 1;
+    let head = match mode {
+        EllipsisMode::Start => 0,
+        // Half, rounded up.
+        EllipsisMode::Middle => avail - avail / 2,
+        EllipsisMode::End => avail,
+    };
+    let (mut out, head) = texts_prefix(texts, head);
+    // The middle gives the tail whatever the head couldn't use.
+    let tail = match mode {
+        EllipsisMode::End => 0,
+        _ => avail - head,
+    };
+    let (tail, tail_width) = texts_suffix(texts, tail);
+    let cut = match mode {
+        EllipsisMode::Start => total - tail_width - 1,
+        _ => head,
+    };
+    out.push(text_at_column(texts, cut).with_text(ELLIPSIS.to_string()));
+    out.extend(tail);
+    out
+}
 
-    // start_index and end_index are set using char_indices, so this should
-    // be safe. If a problem is suspected, this will panic instead:
-    //   txt.get(start_index..end_index).unwrap()
-    unsafe { txt.get_unchecked(start_index..end_index) }
+/// The start and end columns of a range over something width columns wide.
+pub(crate) fn column_range<R: RangeBounds<usize>>(r: &R, width: u32) -> (usize, usize) {
+    let a = match r.start_bound() {
+        Bound::Included(i) => *i,
+        Bound::Excluded(i) => i + 1,
+        Bound::Unbounded => 0,
+    };
+    let b = match r.end_bound() {
+        Bound::Included(i) => *i + 1,
+        Bound::Excluded(i) => *i,
+        Bound::Unbounded => width as _,
+    };
+    (a, b)
 }
 
 impl StyledText {
@@ -55,32 +249,176 @@ impl StyledText {
             style,
             text: Rc::new(text),
             width: width as _,
+            tag: None,
+            transparent: false,
+        }
+    }
+
+    /// Create a transparent StyledText of the given width. When a TermRect is
+    /// a layer in a Compositor, whatever is below shows through transparent
+    /// text. Anywhere else, it is just spaces.
+    pub fn transparent(width: u32) -> StyledText {
+        StyledText {
+            transparent: true,
+            ..StyledText::repeat(Style::default(), ' ', width)
+        }
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        self.transparent
+    }
+
+    /// Create a new StyledText, with tabs and control characters dealt with
+    /// according to the policy. StyledText::new keeps them as they are, and
+    /// counts them as zero width.
+    pub fn with_policy(
+        style: Style,
+        text: &str,
+        policy: &TextPolicy,
+    ) -> Result<StyledText, ControlCharError> {
+        let mut out = String::with_capacity(text.len());
+        let mut column = policy.start_column;
+        for (index, c) in text.char_indices() {
+            match (c, policy.tab_width) {
+                ('\t', Some(tab_width)) => {
+                    let tab_width = tab_width.max(1);
+                    let n = tab_width - column % tab_width;
+                    out.push_str(&" ".repeat(n as usize));
+                    column += n;
+                }
+                (c, _) if c.is_control() => match policy.controls {
+                    ControlPolicy::Replace => {
+                        let len = out.len();
+                        push_caret(&mut out, c);
+                        column += (out.len() - len) as u32;
+                    }
+                    ControlPolicy::Strip => {}
+                    ControlPolicy::Reject => return Err(ControlCharError { ch: c, index }),
+                },
+                (c, _) => {
+                    out.push(c);
+                    column += UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+                }
+            }
+        }
+        Ok(StyledText::new(style, out))
+    }
+
+    /// The same text, tagged. The tag stays with the text when it is drawn,
+    /// so TermRect::tag_at can find it.
+    pub fn with_tag(&self, tag: Tag) -> StyledText {
+        StyledText {
+            tag: Some(tag),
+            ..self.clone()
+        }
+    }
+
+    /// Different text with the same style and tag.
+    pub(crate) fn with_text(&self, text: String) -> StyledText {
+        StyledText {
+            width: UnicodeWidthStr::width(&text as &str) as u32,
+            text: Rc::new(text),
+            ..self.clone()
+        }
+    }
+
+    pub fn tag(&self) -> Option<Tag> {
+        self.tag
+    }
+
+    /// Create a StyledText of the given width made of copies of c. If c is
+    /// double width and doesn't fit evenly, the remainder is padded with
+    /// spaces.
+    pub fn repeat(style: Style, c: char, width: u32) -> StyledText {
+        let c = match UnicodeWidthChar::width(c) {
+            Some(w) if w > 0 => c,
+            _ => ' ',
+        };
+        let cw = UnicodeWidthChar::width(c).unwrap_or(1) as u32;
+        let mut text = c.to_string().repeat((width / cw) as usize);
+        text.push_str(&" ".repeat((width % cw) as usize));
+        StyledText {
+            style,
+            text: Rc::new(text),
+            width,
+            tag: None,
+            transparent: false,
+        }
+    }
+
+    /// Shorten the text to fit in width, replacing what was cut out with an
+    /// ellipsis. Text that already fits is returned as it is. A double-width
+    /// char is never cut in half, so the result can be one narrower than
+    /// width.
+    pub fn truncate(&self, width: u32, mode: EllipsisMode) -> StyledText {
+        if self.width <= width {
+            return self.clone();
         }
+        let texts = truncate_texts(slice::from_ref(self), width, mode);
+        self.with_text(texts.iter().map(|t| &t.text as &str).collect())
     }
 
     /// Slice the string returning a new StyledText with the same style. Slicing is done by
     /// width, rather than by byte or by char. So the returned slice should have exactly the
-    /// width specified.
+    /// width specified: a double-width char cut in half at either end becomes a space.
     pub fn slice<R: RangeBounds<usize>>(&self, r: R) -> StyledText
     where
         String: std::ops::Index<R>,
     {
-        let a = match r.start_bound() {
-            Bound::Included(i) => *i,
-            Bound::Excluded(i) => i + 1,
-            Bound::Unbounded => 0,
-        };
-        let b = match r.end_bound() {
-            Bound::Included(i) => *i + 1,
-            Bound::Excluded(i) => *i,
-            Bound::Unbounded => self.width as _,
-        };
-        let sliced = width_slice(&self.text, a, b).to_string();
+        let (inner, lead, trail) = self.slice_padded(r);
+        if lead == 0 && trail == 0 {
+            return inner;
+        }
+        let mut text = " ".repeat(lead as usize);
+        text.push_str(&inner.text);
+        text.push_str(&" ".repeat(trail as usize));
         StyledText {
-            style: self.style,
-            text: Rc::new(sliced),
-            width: (b - a) as u32,
+            text: Rc::new(text),
+            width: lead + inner.width + trail,
+            ..inner
+        }
+    }
+
+    /// Slice by width, like slice, but leave out any double-width char cut
+    /// in half. The slice comes back with the number of columns missing
+    /// before and after it, for the caller to fill in. Columns past the end
+    /// of the text count as missing after it.
+    pub fn slice_padded<R: RangeBounds<usize>>(&self, r: R) -> (StyledText, u32, u32) {
+        let (a, b) = column_range(&r, self.width);
+        let (a, b) = (a as u32, (b as u32).max(a as u32));
+        let mut text = String::new();
+        let (mut col, mut lead, mut width) = (0, 0, 0);
+        let mut kept = false;
+        for c in self.text.chars() {
+            let w = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+            if w == 0 {
+                // zero width chars go with the char before them
+                if kept {
+                    text.push(c);
+                }
+                continue;
+            }
+            if col >= b {
+                break;
+            }
+            let end = col + w;
+            kept = col >= a && end <= b;
+            if kept {
+                text.push(c);
+                width += w;
+            } else if col < a && end > a {
+                lead = end.min(b) - a;
+            }
+            col = end;
         }
+        let inner = StyledText {
+            style: self.style,
+            text: Rc::new(text),
+            width,
+            tag: self.tag,
+            transparent: self.transparent,
+        };
+        (inner, lead, b - a - lead - width)
     }
 }
 
@@ -92,7 +430,7 @@ impl HasSize for StyledText {
 
 impl PaintableWidget for StyledText {
     fn draw_into<R: RawPaintable>(&self, target: &mut R, pos: (u32, u32)) {
-        target.draw_text_at(pos, &self);
+        target.draw_text_at(pos, self);
     }
 }
 
@@ -101,6 +439,7 @@ mod test {
     use super::*;
     use std::clone::Clone;
     use std::fmt::Debug;
+    use std::ops::Range;
 
     #[test]
     fn empty_styledtext() {
@@ -124,6 +463,91 @@ mod test {
         );
     }
 
+    fn truncated(s: &str, width: u32, mode: EllipsisMode) -> (String, u32) {
+        let t = StyledText::new(Style::default(), s.to_string()).truncate(width, mode);
+        (t.text.to_string(), t.width)
+    }
+
+    #[test]
+    fn truncation() {
+        use self::EllipsisMode::*;
+
+        assert_eq!(truncated("abcdef", 6, End), ("abcdef".to_string(), 6));
+        assert_eq!(truncated("abcdef", 4, End), ("abc…".to_string(), 4));
+        assert_eq!(truncated("abcdef", 4, Start), ("…def".to_string(), 4));
+        assert_eq!(truncated("abcdef", 4, Middle), ("ab…f".to_string(), 4));
+        assert_eq!(truncated("abcdef", 1, Middle), ("…".to_string(), 1));
+        assert_eq!(truncated("abcdef", 0, End), ("".to_string(), 0));
+
+        // double width chars are never split
+        assert_eq!(truncated("台北1234", 4, End), ("台…".to_string(), 3));
+        assert_eq!(truncated("1234台北", 4, Start), ("…北".to_string(), 3));
+        assert_eq!(truncated("台北台北", 6, Middle), ("台…北".to_string(), 5));
+    }
+
+    #[test]
+    fn truncation_of_texts() {
+        use style::StyleAttr;
+
+        let bold = Style::default().set(StyleAttr::Bold);
+        let texts = vec![
+            StyledText::new(Style::default(), "/usr/".to_string()),
+            StyledText::new(bold, "share".to_string()),
+            StyledText::new(Style::default(), "/file".to_string()),
+        ];
+        let out = truncate_texts(&texts, 10, EllipsisMode::Middle);
+        let out: Vec<_> = out
+            .iter()
+            .map(|t| (&*t.text as &str, t.style == bold))
+            .collect();
+        assert_eq!(out, vec![("/usr/", false), ("…", true), ("file", false)]);
+    }
+
+    #[test]
+    fn policies() {
+        let with = |s: &str, policy: &TextPolicy| {
+            StyledText::with_policy(Style::default(), s, policy)
+                .map(|t| (t.text.to_string(), t.width))
+        };
+        let policy = TextPolicy::default();
+        assert_eq!(with("a\tb", &policy), Ok(("a       b".to_string(), 9)));
+        assert_eq!(
+            with(
+                "台\tb",
+                &TextPolicy {
+                    tab_width: Some(4),
+                    start_column: 1,
+                    ..policy
+                }
+            ),
+            Ok(("台 b".to_string(), 4))
+        );
+        assert_eq!(
+            with("\x1b[2J\r\n\x7f\u{9b}", &policy),
+            Ok(("^[[2J^M^J^?M-^[".to_string(), 15))
+        );
+
+        let strip = TextPolicy {
+            tab_width: None,
+            controls: ControlPolicy::Strip,
+            ..policy
+        };
+        assert_eq!(with("a\tb\x1b", &strip), Ok(("ab".to_string(), 2)));
+
+        let reject = TextPolicy {
+            controls: ControlPolicy::Reject,
+            ..policy
+        };
+        assert_eq!(with("a\tb", &reject), Ok(("a       b".to_string(), 9)));
+        assert_eq!(
+            with("ab\x1b", &reject),
+            Err(ControlCharError {
+                ch: '\x1b',
+                index: 2
+            })
+        );
+    }
+
     #[test]
     fn slicing() {
         slice_test("asdf", .., "asdf", 4);
@@ -132,16 +556,28 @@ mod test {
         slice_test("台北1234", .., "台北1234", 8); // 2 double-width chars
         slice_test("台北1234", 0..2, "台", 2); // 2 double-width chars
 
-        // TODO: This keeps 1 double width char, even though we only asked for 1 width.
-        //       Is this what terminal emulators do?
-        slice_test("台北1234", 0..1, "台", 1);
-
-        // TODO: This tries to start a slice in the middle of one char. What to do?
-        //       We definitely don't want to crash.
-        slice_test("台北1234", 1..2, "", 1);
+        // half a double width char is padded with a space
+        slice_test("台北1234", 0..1, " ", 1);
+        slice_test("台北1234", 1..2, " ", 1);
+        slice_test("台北1234", 1..5, " 北1", 4);
+        slice_test("a\u{301}b", 0..1, "a\u{301}", 1);
 
         slice_test("台北1234", 2..4, "北", 2);
         slice_test("ＱＲＳ12", .., "ＱＲＳ12", 8);
         slice_test("ｱｲｳ1234", .., "ｱｲｳ1234", 7); // 3 single-width chars
     }
+
+    #[test]
+    fn padded_slicing() {
+        let padded = |r: Range<usize>| {
+            let (t, lead, trail) =
+                StyledText::new(Style::default(), "台北12".to_string()).slice_padded(r);
+            (t.text.to_string(), t.width, lead, trail)
+        };
+        assert_eq!(padded(1..5), ("北1".to_string(), 3, 1, 0));
+        assert_eq!(padded(0..3), ("台".to_string(), 2, 0, 1));
+        assert_eq!(padded(1..2), ("".to_string(), 0, 1, 0));
+        assert_eq!(padded(4..8), ("12".to_string(), 2, 0, 2));
+        assert_eq!(padded(3..3), ("".to_string(), 0, 0, 0));
+    }
 }
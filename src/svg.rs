@@ -0,0 +1,226 @@
+use html::escape_xml;
+use palette::{hex, Palette};
+use style::StyleAttr;
+use termrect::{HasSize, TermRect};
+
+use unicode_width::UnicodeWidthStr;
+
+/// FontMetrics says how big a cell is in an SVG, and what font the text is
+/// drawn in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontMetrics {
+    pub cell_width: f64,
+    pub cell_height: f64,
+    /// How far down the cell the text baseline is.
+    pub baseline: f64,
+    pub font_size: f64,
+    /// A CSS font-family list. Should be monospace.
+    pub font_family: String,
+}
+
+impl Default for FontMetrics {
+    /// A 14px monospace font.
+    fn default() -> FontMetrics {
+        FontMetrics {
+            cell_width: 8.4,
+            cell_height: 17.0,
+            baseline: 13.0,
+            font_size: 14.0,
+            font_family: "ui-monospace, Menlo, Consolas, monospace".to_string(),
+        }
+    }
+}
+
+// The window frame: padding around the text, and the title bar.
+const PADDING: f64 = 10.0;
+const TITLE_BAR: f64 = 28.0;
+
+/// Format a number with at most 2 decimal places and no trailing zeros, so
+/// the output doesn't depend on float rounding noise.
+fn num(x: f64) -> String {
+    let s = format!("{:.2}", x);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+impl TermRect {
+    /// The contents as an SVG image, one cell per metrics.cell_width by
+    /// cell_height. The same TermRect always gives the same SVG.
+    pub fn to_svg(&self, metrics: &FontMetrics, palette: &Palette) -> String {
+        self.svg(metrics, palette, None)
+    }
+
+    /// Like to_svg, inside a window frame with a title bar.
+    pub fn to_svg_window(&self, metrics: &FontMetrics, palette: &Palette, title: &str) -> String {
+        self.svg(metrics, palette, Some(title))
+    }
+
+    fn svg(&self, metrics: &FontMetrics, palette: &Palette, title: Option<&str>) -> String {
+        let (cols, rows) = self.size();
+        let (cw, ch) = (metrics.cell_width, metrics.cell_height);
+        let (w, h) = (cols as f64 * cw, rows as f64 * ch);
+        let (ox, oy, total_w, total_h) = match title {
+            Some(_) => (
+                PADDING,
+                TITLE_BAR + PADDING,
+                w + 2.0 * PADDING,
+                h + TITLE_BAR + 2.0 * PADDING,
+            ),
+            None => (0.0, 0.0, w, h),
+        };
+
+        let mut out = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1}\" \
+             viewBox=\"0 0 {0} {1}\" font-family=\"{2}\" font-size=\"{3}\">\n",
+            num(total_w),
+            num(total_h),
+            escape_xml(&metrics.font_family),
+            num(metrics.font_size)
+        );
+        if let Some(title) = title {
+            out += &format!(
+                "<rect width=\"{}\" height=\"{}\" rx=\"8\" fill=\"{}\" stroke=\"#808080\"/>\n",
+                num(total_w),
+                num(total_h),
+                hex(palette.bg)
+            );
+            for (i, color) in ["#ff5f57", "#febc2e", "#28c840"].iter().enumerate() {
+                out += &format!(
+                    "<circle cx=\"{}\" cy=\"{}\" r=\"6\" fill=\"{}\"/>\n",
+                    num(PADDING + 8.0 + 20.0 * i as f64),
+                    num(TITLE_BAR / 2.0 + 2.0),
+                    color
+                );
+            }
+            out += &format!(
+                "<text x=\"{}\" y=\"{}\" fill=\"{}\" text-anchor=\"middle\">{}</text>\n",
+                num(total_w / 2.0),
+                num(TITLE_BAR / 2.0 + 2.0 + metrics.font_size / 3.0),
+                hex(palette.fg),
+                escape_xml(title)
+            );
+        } else {
+            out += &format!(
+                "<rect width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                num(w),
+                num(h),
+                hex(palette.bg)
+            );
+        }
+        out += &format!("<g transform=\"translate({},{})\">\n", num(ox), num(oy));
+
+        // Backgrounds first, so no text is drawn over by a later one.
+        let lines = self.lines();
+        for (y, line) in lines.iter().enumerate() {
+            for (x, style, text) in line.runs() {
+                let (_, bg) = palette.resolve(style);
+                if bg == palette.bg {
+                    continue;
+                }
+                let width = UnicodeWidthStr::width(&text as &str);
+                out += &format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\"/>\n",
+                    num(x as f64 * cw),
+                    num(y as f64 * ch),
+                    num(width as f64 * cw),
+                    num(ch),
+                    hex(bg)
+                );
+            }
+        }
+
+        for (y, line) in lines.iter().enumerate() {
+            for (x, style, text) in line.runs() {
+                let decorated =
+                    style.isset(StyleAttr::Underline) || style.isset(StyleAttr::CrossedOut);
+                if text.trim().is_empty() && !decorated {
+                    continue;
+                }
+                let (fg, _) = palette.resolve(style);
+                let width = UnicodeWidthStr::width(&text as &str);
+                out += &format!(
+                    "<text x=\"{}\" y=\"{}\" textLength=\"{}\" lengthAdjust=\"spacingAndGlyphs\" \
+                     fill=\"{}\"",
+                    num(x as f64 * cw),
+                    num(y as f64 * ch + metrics.baseline),
+                    num(width as f64 * cw),
+                    hex(fg)
+                );
+                if style.isset(StyleAttr::Bold) {
+                    out += " font-weight=\"bold\"";
+                }
+                if style.isset(StyleAttr::Italic) {
+                    out += " font-style=\"italic\"";
+                }
+                if decorated {
+                    let mut lines = Vec::new();
+                    if style.isset(StyleAttr::Underline) {
+                        lines.push("underline");
+                    }
+                    if style.isset(StyleAttr::CrossedOut) {
+                        lines.push("line-through");
+                    }
+                    out += &format!(" text-decoration=\"{}\"", lines.join(" "));
+                }
+                out += &format!(" xml:space=\"preserve\">{}</text>\n", escape_xml(&text));
+            }
+        }
+        out += "</g>\n</svg>\n";
+        out
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::{Color, Style};
+    use styledtext::StyledText;
+    use termrect::RawPaintable;
+
+    #[test]
+    fn svg() {
+        let mut tr = TermRect::new((4, 2));
+        let style = Style::default()
+            .set_fg(Color::Indexed(2))
+            .set_bg(Color::Indexed(4))
+            .set(StyleAttr::Bold);
+        tr.draw_text_at((1, 1), &StyledText::new(style, "a<".to_string()));
+        let metrics = FontMetrics {
+            cell_width: 10.0,
+            cell_height: 20.0,
+            baseline: 15.0,
+            font_size: 16.0,
+            font_family: "monospace".to_string(),
+        };
+        let palette = Palette::default();
+        assert_eq!(
+            tr.to_svg(&metrics, &palette),
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"40\" height=\"40\" \
+             viewBox=\"0 0 40 40\" font-family=\"monospace\" font-size=\"16\">\n\
+             <rect width=\"40\" height=\"40\" fill=\"#000000\"/>\n\
+             <g transform=\"translate(0,0)\">\n\
+             <rect x=\"10\" y=\"20\" width=\"20\" height=\"20\" fill=\"#0000ee\"/>\n\
+             <text x=\"10\" y=\"35\" textLength=\"20\" lengthAdjust=\"spacingAndGlyphs\" \
+             fill=\"#00cd00\" font-weight=\"bold\" xml:space=\"preserve\">a&lt;</text>\n\
+             </g>\n</svg>\n"
+        );
+
+        let framed = tr.to_svg_window(&metrics, &palette, "a & b");
+        assert!(framed.contains("width=\"60\" height=\"88\""));
+        assert!(framed.contains(">a &amp; b</text>"));
+        assert!(framed.contains("<g transform=\"translate(10,38)\">"));
+        assert_eq!(framed, tr.to_svg_window(&metrics, &palette, "a & b"));
+
+        tr.draw_text_at((0, 0), &StyledText::new(style, "\x1b[2J".to_string()));
+        let svg = tr.to_svg_window(&metrics, &palette, "\x1b]0;x\x07");
+        assert!(!svg.contains(|c: char| c.is_control() && c != '\n'));
+        assert!(svg.contains(">\u{fffd}]0;x\u{fffd}</text>"));
+
+        assert_eq!(num(8.4 * 3.0), "25.2");
+        assert_eq!(num(-0.001), "0");
+    }
+}
@@ -1,17 +1,41 @@
+use cursor::{Cursor, CursorShape};
 use style::{Style, StyleFromTo};
 use styledtext::StyledText;
-use termrect::{HasSize, RawPaintable};
+use termrect::{HasSize, RawPaintable, Rect};
 
 use std;
 use std::io::Write;
+use std::ops::{Deref, DerefMut};
 use termion;
+use unicode_width::UnicodeWidthChar;
 
 pub struct Terminal<W: Write> {
     size: (u32, u32),
     current_style: Style,
+    use_rep: bool,
+    raw_controls: bool,
+    // The cursor asked for by set_cursor, and what was last written for it.
+    // None means unknown, so it gets written next time.
+    cursor: Option<Cursor>,
+    shown_visible: Option<bool>,
+    shown_shape: Option<(CursorShape, bool)>,
+    // Output is buffered here between begin_frame and end_frame.
+    frame: Option<Vec<u8>>,
     w: W,
 }
 
+/// Move the cursor to pos, counted from 0. Positions too big for the escape
+/// sequence are clamped.
+fn goto_seq(pos: (u32, u32)) -> termion::cursor::Goto {
+    let max = u16::MAX as u32 - 1;
+    termion::cursor::Goto(1 + pos.0.min(max) as u16, 1 + pos.1.min(max) as u16)
+}
+
+/// The number of bytes needed to write n in decimal.
+fn digits(n: u32) -> usize {
+    n.to_string().len()
+}
+
 impl<W: Write> Terminal<W> {
     pub fn new(mut w: W, size: (u32, u32)) -> Terminal<W> {
         let current_style = Style::default();
@@ -19,12 +43,229 @@ impl<W: Write> Terminal<W> {
         Terminal {
             size,
             current_style,
+            use_rep: true,
+            raw_controls: false,
+            cursor: None,
+            shown_visible: None,
+            shown_shape: None,
+            frame: None,
             w,
         }
     }
+
+    /// Choose whether to use REP (repeat the preceding character) for long
+    /// runs of the same character. This is on by default, but not every
+    /// terminal supports it.
+    pub fn set_use_rep(&mut self, use_rep: bool) {
+        self.use_rep = use_rep;
+    }
+
+    /// Choose whether control chars in text are written out as they are.
+    /// By default they are left out, so text can't move the cursor or send
+    /// escape sequences of its own. They take up no columns either way; use
+    /// StyledText::with_policy to make them visible.
+    pub fn set_raw_controls(&mut self, raw_controls: bool) {
+        self.raw_controls = raw_controls;
+    }
+
+    /// Change the size, for when the terminal has been resized.
+    pub fn resize(&mut self, size: (u32, u32)) {
+        self.size = size;
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.w
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// Give back the writer. Any unfinished frame is dropped.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+
+    /// Write out the cursor and flush. Inside a frame, the cursor is written
+    /// into the frame instead.
     pub fn flush(&mut self) -> Result<(), std::io::Error> {
+        self.write_cursor();
         self.w.flush()
     }
+
+    /// Start buffering output for a frame. Nothing is written until
+    /// end_frame, which sends the whole frame at once wrapped in a
+    /// synchronized update (DEC private mode 2026), so terminals that support
+    /// it never show a partially drawn frame. Does nothing if a frame is
+    /// already started.
+    pub fn begin_frame(&mut self) {
+        if self.frame.is_none() {
+            self.frame = Some(Vec::new());
+        }
+    }
+
+    /// Write out and flush the frame started by begin_frame.
+    pub fn end_frame(&mut self) -> Result<(), std::io::Error> {
+        self.write_cursor();
+        if let Some(buf) = self.frame.take() {
+            if !buf.is_empty() {
+                let mut out = Vec::with_capacity(buf.len() + 16);
+                out.extend_from_slice(b"\x1b[?2026h");
+                out.extend_from_slice(&buf);
+                out.extend_from_slice(b"\x1b[?2026l");
+                self.w.write_all(&out)?;
+            }
+        }
+        self.w.flush()
+    }
+
+    /// Start a frame that ends when the returned guard is dropped. See
+    /// begin_frame.
+    pub fn frame(&mut self) -> Frame<'_, W> {
+        self.begin_frame();
+        Frame { terminal: self }
+    }
+
+    /// Where output goes: the frame buffer if a frame is started, otherwise
+    /// straight to the writer.
+    fn out(&mut self) -> &mut dyn Write {
+        match self.frame {
+            Some(ref mut buf) => buf,
+            None => &mut self.w,
+        }
+    }
+
+    fn goto(&mut self, pos: (u32, u32)) {
+        // Don't let the cursor be seen jumping around while drawing.
+        if self.shown_visible == Some(true) {
+            self.shown_visible = Some(false);
+            write!(self.out(), "{}", termion::cursor::Hide).unwrap();
+        }
+        // TODO: Track cursor position and only move if necessary
+        write!(self.out(), "{}", goto_seq(pos)).unwrap();
+    }
+
+    fn set_style(&mut self, style: Style) {
+        if self.current_style != style {
+            let from_to = StyleFromTo {
+                from: self.current_style,
+                to: style,
+            };
+            write!(self.out(), "{}", from_to).unwrap();
+            self.current_style = style;
+        }
+    }
+
+    /// Put the cursor where set_cursor asked for it, writing only what changed
+    /// since it was last shown.
+    fn write_cursor(&mut self) {
+        let cursor = match self.cursor {
+            Some(c) => c,
+            None => return,
+        };
+        if !cursor.visible {
+            if self.shown_visible != Some(false) {
+                self.shown_visible = Some(false);
+                write!(self.out(), "{}", termion::cursor::Hide).unwrap();
+            }
+            return;
+        }
+
+        // Drawing moves the cursor, so always put it back. A cursor outside
+        // the terminal goes to the nearest edge.
+        let (x, y) = cursor.pos;
+        let pos = (
+            x.min(self.size.0.saturating_sub(1)),
+            y.min(self.size.1.saturating_sub(1)),
+        );
+        write!(self.out(), "{}", goto_seq(pos)).unwrap();
+        let shape = (cursor.shape, cursor.blinking);
+        if self.shown_shape != Some(shape) {
+            self.shown_shape = Some(shape);
+            // DECSCUSR: odd is blinking, even is steady
+            let n = match cursor.shape {
+                CursorShape::Block => 1,
+                CursorShape::Underline => 3,
+                CursorShape::Bar => 5,
+            } + if cursor.blinking { 0 } else { 1 };
+            write!(self.out(), "\x1b[{} q", n).unwrap();
+        }
+        if self.shown_visible != Some(true) {
+            self.shown_visible = Some(true);
+            write!(self.out(), "{}", termion::cursor::Show).unwrap();
+        }
+    }
+
+    /// Write text at the cursor, collapsing runs of the same character with
+    /// REP when that is shorter.
+    fn write_runs(&mut self, text: &str) {
+        let stripped: String;
+        let text = if !self.raw_controls && text.contains(char::is_control) {
+            stripped = text.chars().filter(|c| !c.is_control()).collect();
+            &stripped
+        } else {
+            text
+        };
+        if !self.use_rep {
+            self.out().write_all(text.as_bytes()).unwrap();
+            return;
+        }
+        let mut chars = text.chars().peekable();
+        while let Some(c) = chars.next() {
+            let mut n = 1;
+            while chars.peek() == Some(&c) {
+                chars.next();
+                n += 1;
+            }
+            let len = c.len_utf8();
+            let rep_len = len + 3 + digits(n - 1);
+            let mut buf = [0; 4];
+            let bytes = c.encode_utf8(&mut buf).as_bytes();
+            // REP repeats the last graphic char, so it can't repeat zero
+            // width chars.
+            if n > 1 && rep_len < n as usize * len && c.width().unwrap_or(0) > 0 {
+                self.out().write_all(bytes).unwrap();
+                write!(self.out(), "\x1b[{}b", n - 1).unwrap();
+            } else {
+                for _ in 0..n {
+                    self.out().write_all(bytes).unwrap();
+                }
+            }
+        }
+    }
+}
+
+/// A frame started by Terminal::frame. Derefs to the Terminal, and ends the
+/// frame when dropped.
+pub struct Frame<'a, W: Write + 'a> {
+    terminal: &'a mut Terminal<W>,
+}
+
+impl<'a, W: Write> Frame<'a, W> {
+    /// End the frame now, returning any error from writing it out.
+    pub fn end(self) -> Result<(), std::io::Error> {
+        // Drop will find the frame already ended and only flush again.
+        self.terminal.end_frame()
+    }
+}
+
+impl<'a, W: Write> Deref for Frame<'a, W> {
+    type Target = Terminal<W>;
+    fn deref(&self) -> &Terminal<W> {
+        self.terminal
+    }
+}
+
+impl<'a, W: Write> DerefMut for Frame<'a, W> {
+    fn deref_mut(&mut self) -> &mut Terminal<W> {
+        self.terminal
+    }
+}
+
+impl<'a, W: Write> Drop for Frame<'a, W> {
+    fn drop(&mut self) {
+        let _ = self.terminal.end_frame();
+    }
 }
 
 impl<W: Write> HasSize for Terminal<W> {
@@ -35,26 +276,229 @@ impl<W: Write> HasSize for Terminal<W> {
 
 impl<W: Write> RawPaintable for Terminal<W> {
     fn draw_text_at(&mut self, pos: (u32, u32), text: &StyledText) -> bool {
-        // TODO: Track cursor position and only move if necessary
-        write!(
-            self.w,
-            "{}",
-            termion::cursor::Goto(1 + pos.0 as u16, 1 + pos.1 as u16)
-        )
-        .unwrap();
-        if self.current_style != text.style {
-            write!(
-                self.w,
-                "{}",
-                StyleFromTo {
-                    from: self.current_style,
-                    to: text.style
-                }
-            )
-            .unwrap();
-            self.current_style = text.style;
+        self.goto(pos);
+        self.set_style(text.style);
+        if !text.style.blank_is_erased() {
+            self.write_runs(&text.text);
+            return true;
+        }
+
+        // Trailing blanks can be erased instead of written out.
+        let content = text.text.trim_end_matches(' ');
+        let blanks = (text.text.len() - content.len()) as u32;
+        let to_edge = pos.0 + text.width >= self.size.0;
+        self.write_runs(content);
+        if blanks > 0 && to_edge {
+            // EL: erase to the end of the line
+            write!(self.out(), "{}", termion::clear::UntilNewline).unwrap();
+        } else if blanks > 0 && 3 + digits(blanks) < blanks as usize {
+            // ECH: erase characters, without moving the cursor
+            write!(self.out(), "\x1b[{}X", blanks).unwrap();
+        } else {
+            self.out()
+                .write_all(" ".repeat(blanks as usize).as_bytes())
+                .unwrap();
+        }
+        true
+    }
+
+    fn clear_rect(&mut self, rect: Rect, style: Style) -> bool {
+        let rect = rect.clip(self.size);
+        if rect.is_empty() {
+            return false;
+        }
+        for y in rect.pos.1..rect.bottom() {
+            self.goto((rect.pos.0, y));
+            self.set_style(style);
+            if !style.blank_is_erased() {
+                // Erased cells wouldn't show the style, so write spaces.
+                self.write_runs(&" ".repeat(rect.size.0 as usize));
+            } else if rect.right() >= self.size.0 {
+                // EL: erase to the end of the line
+                write!(self.out(), "{}", termion::clear::UntilNewline).unwrap();
+            } else {
+                // ECH: erase characters, without moving the cursor
+                write!(self.out(), "\x1b[{}X", rect.size.0).unwrap();
+            }
+        }
+        true
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) -> bool {
+        if self.cursor == Some(cursor) {
+            return false;
+        }
+        self.cursor = Some(cursor);
+        true
+    }
+
+    fn clear_to_end_of_line(&mut self, pos: (u32, u32), style: Style) -> bool {
+        if pos.0 >= self.size.0 || pos.1 >= self.size.1 {
+            return false;
+        }
+        self.goto(pos);
+        self.set_style(style);
+        if style.blank_is_erased() {
+            write!(self.out(), "{}", termion::clear::UntilNewline).unwrap();
+        } else {
+            self.write_runs(&" ".repeat((self.size.0 - pos.0) as usize));
         }
-        write!(self.w, "{}", text.text).unwrap();
         true
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::StyleAttr;
+
+    fn output_of<F: FnOnce(&mut Terminal<Vec<u8>>)>(f: F) -> String {
+        let mut t = Terminal::new(Vec::new(), (10, 3));
+        t.w.clear();
+        f(&mut t);
+        String::from_utf8(t.w).unwrap()
+    }
+
+    #[test]
+    fn clear() {
+        let out = output_of(|t| {
+            t.clear_to_end_of_line((4, 1), Style::default());
+        });
+        assert_eq!(out, "\x1b[2;5H\x1b[K");
+
+        let out = output_of(|t| {
+            t.clear_rect(Rect::new((2, 1), (3, 5)), Style::default());
+        });
+        assert_eq!(out, "\x1b[2;3H\x1b[3X\x1b[3;3H\x1b[3X");
+
+        // erasing wouldn't show an underline, so spaces are written
+        let underline = Style::default().set(StyleAttr::Underline);
+        let out = output_of(|t| {
+            t.clear_to_end_of_line((7, 0), underline);
+            t.clear_rect(Rect::new((0, 1), (2, 1)), underline);
+        });
+        assert_eq!(
+            out,
+            format!("\x1b[1;8H{}   \x1b[2;1H  ", termion::style::Underline)
+        );
+    }
+
+    #[test]
+    fn trailing_blanks() {
+        let text = |s: &str| StyledText::new(Style::default(), s.to_string());
+
+        // blanks reaching the right edge become EL
+        let out = output_of(|t| {
+            t.draw_text_at((2, 0), &text("ab      "));
+        });
+        assert_eq!(out, "\x1b[1;3Hab\x1b[K");
+
+        // long blanks in the middle become ECH, short ones are written
+        let out = output_of(|t| {
+            t.draw_text_at((0, 0), &text("a      "));
+            t.draw_text_at((0, 1), &text("a  "));
+        });
+        assert_eq!(out, "\x1b[1;1Ha\x1b[6X\x1b[2;1Ha  ");
+
+        // blanks with a background are written out
+        let blue = Style::default().set_bg(::Color::Indexed(4));
+        let out = output_of(|t| {
+            t.set_style(blue);
+            t.w.clear();
+            t.draw_text_at((8, 0), &StyledText::new(blue, "  ".to_string()));
+        });
+        assert_eq!(out, "\x1b[1;9H  ");
+    }
+
+    #[test]
+    fn repeated_chars() {
+        let out = output_of(|t| {
+            t.draw_text_at(
+                (0, 0),
+                &StyledText::new(Style::default(), "x──────".to_string()),
+            );
+        });
+        assert_eq!(out, "\x1b[1;1Hx─\x1b[5b");
+
+        let out = output_of(|t| {
+            t.set_use_rep(false);
+            t.draw_text_at(
+                (0, 0),
+                &StyledText::new(Style::default(), "xxxxxxx".to_string()),
+            );
+        });
+        assert_eq!(out, "\x1b[1;1Hxxxxxxx");
+    }
+
+    #[test]
+    fn control_chars() {
+        let text = StyledText::new(Style::default(), "a\x1b[2Jb\r\u{9b}c".to_string());
+        let out = output_of(|t| {
+            t.draw_text_at((0, 0), &text);
+        });
+        assert_eq!(out, "\x1b[1;1Ha[2Jbc");
+
+        let out = output_of(|t| {
+            t.set_raw_controls(true);
+            t.draw_text_at((0, 0), &text);
+        });
+        assert_eq!(out, "\x1b[1;1Ha\x1b[2Jb\r\u{9b}c");
+    }
+
+    #[test]
+    fn frames() {
+        let text = StyledText::new(Style::default(), "hi".to_string());
+        let out = output_of(|t| {
+            {
+                let mut f = t.frame();
+                f.draw_text_at((0, 0), &text);
+                f.draw_text_at((0, 1), &text);
+                assert!(f.w.is_empty());
+            }
+            // empty frames write nothing
+            t.begin_frame();
+            t.end_frame().unwrap();
+        });
+        assert_eq!(out, "\x1b[?2026h\x1b[1;1Hhi\x1b[2;1Hhi\x1b[?2026l");
+    }
+
+    #[test]
+    fn cursor() {
+        let text = StyledText::new(Style::default(), "hi".to_string());
+        let out = output_of(|t| {
+            // nothing is written until a cursor is set
+            t.flush().unwrap();
+            t.set_cursor(Cursor::at((1, 2)));
+            t.flush().unwrap();
+        });
+        assert_eq!(out, "\x1b[3;2H\x1b[1 q\x1b[?25h");
+
+        // far off positions are clamped to the terminal
+        let out = output_of(|t| {
+            t.set_cursor(Cursor::at((70000, 1)).offset((u32::MAX, 0)));
+            t.flush().unwrap();
+        });
+        assert_eq!(out, "\x1b[2;10H\x1b[1 q\x1b[?25h");
+
+        let out = output_of(|t| {
+            t.set_cursor(Cursor::at((1, 2)));
+            t.flush().unwrap();
+            t.w.clear();
+            {
+                let mut f = t.frame();
+                f.draw_text_at((5, 0), &text);
+                f.set_cursor(Cursor {
+                    shape: CursorShape::Bar,
+                    blinking: false,
+                    ..Cursor::at((7, 0))
+                });
+            }
+            t.set_cursor(Cursor::default());
+            t.flush().unwrap();
+        });
+        assert_eq!(
+            out,
+            "\x1b[?2026h\x1b[?25l\x1b[1;6Hhi\x1b[1;8H\x1b[6 q\x1b[?25h\x1b[?2026l\x1b[?25l"
+        );
+    }
+}
@@ -1,9 +1,22 @@
+use cursor::Cursor;
 use delta::{Delta, Delta::*};
 use line::Line;
-use style::Style;
-use styledtext::StyledText;
+use style::{Style, StylePatch};
+use styledline::StyledLine;
+use styledtext::{StyledText, Tag};
 
-use std::iter::repeat;
+/// The most cells a TermRect read from outside, such as from a file, can
+/// have.
+const MAX_CELLS: u64 = 1 << 24;
+
+/// Whether a size read from outside is small enough to make a TermRect of:
+/// each side fits in a u16, as on a real terminal, and there are at most
+/// MAX_CELLS cells.
+pub(crate) fn is_sane_size(size: (u32, u32)) -> bool {
+    size.0 <= u16::MAX as u32
+        && size.1 <= u16::MAX as u32
+        && size.0 as u64 * size.1 as u64 <= MAX_CELLS
+}
 
 /// TermRect is a representation of a rectangle of characters in a terminal
 /// grid. It keeps track of changes between calls to draw_delta_into, to always
@@ -13,6 +26,8 @@ pub struct TermRect {
     size: (u32, u32),
     lines: Vec<Line>,
     delta: Delta,
+    cursor: Cursor,
+    cursor_changed: bool,
 }
 
 impl TermRect {
@@ -21,9 +36,121 @@ impl TermRect {
     pub fn new(size: (u32, u32)) -> TermRect {
         TermRect {
             size,
-            lines: repeat(Line::new(size.0)).take(size.1 as _).collect(),
+            lines: vec![Line::new(size.0); size.1 as usize],
             // TODO: Should this be Range(0, size.1) instead?
             delta: Unchanged,
+            cursor: Cursor::default(),
+            cursor_changed: false,
+        }
+    }
+
+    /// Create a new TermRect that is all transparent, for use as a layer in a
+    /// Compositor.
+    pub fn new_transparent(size: (u32, u32)) -> TermRect {
+        TermRect {
+            size,
+            lines: vec![Line::filled(StyledText::transparent(size.0)); size.1 as usize],
+            delta: Unchanged,
+            cursor: Cursor::default(),
+            cursor_changed: false,
+        }
+    }
+
+    /// Restyle everything in the rect with the patch, without changing the
+    /// text. Return true if something changed.
+    pub fn apply_style(&mut self, rect: Rect, patch: &StylePatch) -> bool {
+        let rect = rect.clip(self.size);
+        let mut changed = false;
+        for y in rect.pos.1..rect.bottom() {
+            if self.lines[y as usize].apply_style(rect.pos.0, rect.right(), patch) {
+                self.delta.add(y as usize);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    /// The tag of the text drawn at pos, if it was tagged. See
+    /// StyledText::with_tag.
+    pub fn tag_at(&self, pos: (u32, u32)) -> Option<Tag> {
+        self.lines
+            .get(pos.1 as usize)
+            .and_then(|l| l.text_at(pos.0))
+            .and_then(|t| t.tag)
+    }
+
+    pub(crate) fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Whether anything changed since the last draw_delta_into.
+    pub(crate) fn has_changes(&self) -> bool {
+        self.delta != Unchanged || self.cursor_changed
+    }
+
+    pub fn is_wrapped(&self, y: u32) -> bool {
+        match self.lines.get(y as usize) {
+            Some(l) => l.wrapped,
+            None => false,
+        }
+    }
+
+    /// Move every line up by one, with a blank line coming in at the bottom.
+    pub(crate) fn scroll_up(&mut self, style: Style) {
+        if self.lines.is_empty() {
+            return;
+        }
+        self.lines.remove(0);
+        self.lines
+            .push(Line::filled(StyledText::repeat(style, ' ', self.size.0)));
+        for l in &mut self.lines {
+            l.mark_all_changed();
+        }
+        self.delta.add_range(0..self.lines.len());
+    }
+
+    /// The cursor set by set_cursor. Hidden by default.
+    pub fn cursor(&self) -> Cursor {
+        self.cursor
+    }
+}
+
+/// Rect is a rectangle of cells, given by the position of its top left corner
+/// and its width, height.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub pos: (u32, u32),
+    pub size: (u32, u32),
+}
+
+impl Rect {
+    pub fn new(pos: (u32, u32), size: (u32, u32)) -> Rect {
+        Rect { pos, size }
+    }
+
+    /// The column just past the right edge.
+    pub fn right(&self) -> u32 {
+        self.pos.0.saturating_add(self.size.0)
+    }
+
+    /// The row just past the bottom edge.
+    pub fn bottom(&self) -> u32 {
+        self.pos.1.saturating_add(self.size.1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size.0 == 0 || self.size.1 == 0
+    }
+
+    /// Clip the rect so it fits inside something of the given size.
+    pub fn clip(&self, size: (u32, u32)) -> Rect {
+        let pos = (self.pos.0.min(size.0), self.pos.1.min(size.1));
+        Rect {
+            pos,
+            size: (
+                self.right().min(size.0) - pos.0,
+                self.bottom().min(size.1) - pos.1,
+            ),
         }
     }
 }
@@ -39,10 +166,61 @@ pub trait RawPaintable: HasSize {
         self.draw_text_at(pos, &StyledText::new(style, str))
     }
 
-    /// Clear the whole line after pos. Return true if something changed.
+    /// Draw the texts of the line one after the other from pos. Return true
+    /// if something changed.
+    fn draw_rich_at(&mut self, pos: (u32, u32), line: &StyledLine) -> bool {
+        let mut x = pos.0;
+        let mut changed = false;
+        for t in line.texts() {
+            changed |= self.draw_text_at((x, pos.1), t);
+            x += t.width;
+        }
+        changed
+    }
+
+    /// Set the terminal cursor. Return true if something changed. The default
+    /// does nothing, for targets that have no cursor.
+    fn set_cursor(&mut self, _cursor: Cursor) -> bool {
+        false
+    }
+
+    /// Mark whether line y was soft-wrapped, meaning its text carries on to
+    /// the next line. Return true if something changed. The default does
+    /// nothing, for targets that don't keep track.
+    fn set_wrapped(&mut self, _y: u32, _wrapped: bool) -> bool {
+        false
+    }
+
+    /// Fill the rect with copies of the char. Return true if something changed.
+    fn fill_rect(&mut self, rect: Rect, c: char, style: Style) -> bool {
+        let rect = rect.clip(self.size());
+        if rect.is_empty() {
+            return false;
+        }
+        let text = StyledText::repeat(style, c, rect.size.0);
+        let mut changed = false;
+        for y in rect.pos.1..rect.bottom() {
+            changed |= self.draw_text_at((rect.pos.0, y), &text);
+        }
+        changed
+    }
+
+    /// Fill the rect with spaces. Return true if something changed.
+    fn clear_rect(&mut self, rect: Rect, style: Style) -> bool {
+        self.fill_rect(rect, ' ', style)
+    }
+
+    /// Clear the line from pos to the right edge. Return true if something
+    /// changed.
+    fn clear_to_end_of_line(&mut self, pos: (u32, u32), style: Style) -> bool {
+        let width = self.size().0.saturating_sub(pos.0);
+        self.clear_rect(Rect::new(pos, (width, 1)), style)
+    }
+
+    /// Clear the whole line after pos. Return true if something changed. This
+    /// is the same as clear_to_end_of_line.
     fn clear_line(&mut self, pos: (u32, u32), style: Style) -> bool {
-        let spaces = " ".repeat(self.size().0 as _);
-        self.draw_str_at(pos, style, spaces)
+        self.clear_to_end_of_line(pos, style)
     }
 }
 
@@ -83,6 +261,53 @@ impl RawPaintable for TermRect {
             false
         }
     }
+
+    fn draw_rich_at(&mut self, pos: (u32, u32), line: &StyledLine) -> bool {
+        let y = pos.1 as usize;
+        if y < self.lines.len() && self.lines[y].draw_texts_at(pos.0, line.texts()) {
+            self.delta.add(y);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn fill_rect(&mut self, rect: Rect, c: char, style: Style) -> bool {
+        let rect = rect.clip(self.size);
+        if rect.is_empty() {
+            return false;
+        }
+        // Every row shares the same text, so each row ends up as one span.
+        let text = StyledText::repeat(style, c, rect.size.0);
+        let mut changed = false;
+        for y in rect.pos.1 as usize..rect.bottom() as usize {
+            if self.lines[y].draw_text_at(rect.pos.0, &text) {
+                self.delta.add(y);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn set_cursor(&mut self, cursor: Cursor) -> bool {
+        if self.cursor == cursor {
+            return false;
+        }
+        self.cursor = cursor;
+        self.cursor_changed = true;
+        true
+    }
+
+    /// extract_text joins wrapped lines back together.
+    fn set_wrapped(&mut self, y: u32, wrapped: bool) -> bool {
+        match self.lines.get_mut(y as usize) {
+            Some(l) if l.wrapped != wrapped => {
+                l.wrapped = wrapped;
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
 impl HasSize for TermRect {
@@ -96,6 +321,11 @@ impl PaintableWidget for TermRect {
         for (i, l) in self.lines.iter().enumerate() {
             l.draw_into(target, (pos.0, pos.1 + i as u32));
         }
+        // A hidden cursor is left alone, so it doesn't hide a cursor some
+        // other widget placed.
+        if self.cursor.visible {
+            target.set_cursor(self.cursor.offset(pos));
+        }
     }
     fn draw_delta_into<R: RawPaintable>(&mut self, target: &mut R, pos: (u32, u32)) {
         for (i, l) in self.lines.iter_mut().enumerate() {
@@ -103,15 +333,21 @@ impl PaintableWidget for TermRect {
                 l.draw_delta_into(target, (pos.0, pos.1 + i as u32));
             }
         }
+        // Unlike draw_into, this passes on hiding the cursor too.
+        if self.cursor_changed {
+            target.set_cursor(self.cursor.offset(pos));
+        }
         self.mark_none_changed();
     }
 
     fn mark_all_changed(&mut self) {
         self.delta = Range(0, self.size.1 as usize);
+        self.cursor_changed = self.cursor.visible;
     }
 
     fn mark_none_changed(&mut self) {
         self.delta = Unchanged;
+        self.cursor_changed = false;
     }
 }
 
@@ -147,3 +383,92 @@ impl<T: HasTermRect> PaintableWidget for T {
         self.termrect_mut().mark_none_changed()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::StyleAttr;
+    use testing::render_snapshot;
+
+    #[test]
+    fn rect_clip() {
+        let r = Rect::new((2, 3), (10, 10)).clip((8, 5));
+        assert_eq!(r, Rect::new((2, 3), (6, 2)));
+        assert!(Rect::new((9, 0), (3, 3)).clip((8, 5)).is_empty());
+    }
+
+    #[test]
+    fn fill_and_clear() {
+        let mut tr = TermRect::new((6, 4));
+        let bold = Style::default().set(StyleAttr::Bold);
+        assert!(tr.fill_rect(Rect::new((1, 1), (3, 2)), 'x', bold));
+        assert_eq!(tr.delta, Range(1, 3));
+
+        // a double width char fills what it can, then pads with spaces
+        tr.fill_rect(Rect::new((0, 3), (5, 1)), '台', bold);
+        assert_eq!(
+            render_snapshot(&tr),
+            "6x4\n|      |\n| [bold]xxx[/]  |\n| [bold]xxx[/]  |\n|[bold]台台 [/] |\n"
+        );
+
+        assert!(tr.clear_to_end_of_line((2, 1), Style::default()));
+        assert_eq!(
+            render_snapshot(&tr).lines().nth(2),
+            Some("| [bold]x[/]    |")
+        );
+
+        assert!(!tr.clear_rect(Rect::new((0, 4), (6, 1)), Style::default()));
+    }
+
+    #[test]
+    fn tags() {
+        let mut inner = TermRect::new((4, 1));
+        let text = StyledText::new(Style::default(), "abcd".to_string());
+        inner.draw_text_at((0, 0), &text.with_tag(Tag(1)));
+        inner.draw_text_at((2, 0), &text.slice(..1).with_tag(Tag(2)));
+
+        let mut outer = TermRect::new((10, 3));
+        outer.draw_text_at((0, 1), &text.with_tag(Tag(3)));
+        inner.draw_into(&mut outer, (3, 1));
+
+        let tags: Vec<_> = (0..8).map(|x| outer.tag_at((x, 1))).collect();
+        assert_eq!(
+            tags,
+            vec![
+                Some(Tag(3)),
+                Some(Tag(3)),
+                Some(Tag(3)),
+                Some(Tag(1)),
+                Some(Tag(1)),
+                Some(Tag(2)),
+                Some(Tag(1)),
+                None,
+            ]
+        );
+        assert_eq!(outer.tag_at((0, 0)), None);
+        assert_eq!(outer.tag_at((0, 5)), None);
+    }
+
+    #[test]
+    fn cursor() {
+        let mut inner = TermRect::new((3, 2));
+        let mut outer = TermRect::new((10, 10));
+
+        inner.set_cursor(Cursor::at((1, 1)));
+        inner.draw_delta_into(&mut outer, (4, 5));
+        assert_eq!(outer.cursor(), Cursor::at((5, 6)));
+
+        // only changes are passed on by draw_delta_into
+        outer.set_cursor(Cursor::default());
+        inner.draw_delta_into(&mut outer, (4, 5));
+        assert!(!outer.cursor().visible);
+
+        let hidden = Cursor {
+            visible: false,
+            ..Cursor::at((1, 1))
+        };
+        inner.set_cursor(hidden);
+        inner.draw_delta_into(&mut outer, (4, 5));
+        assert_eq!(outer.cursor(), hidden.offset((4, 5)));
+    }
+}
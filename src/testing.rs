@@ -0,0 +1,180 @@
+//! Snapshot testing, behind the "testing" feature. The crate's own tests
+//! use render_snapshot too.
+//!
+//! A snapshot is a TermRect rendered as text: its size, then each line
+//! between | bars, with styled text in markup (see parse_markup), and the
+//! cursor if it's visible. assert_termrect_snapshot! compares that with a
+//! file under tests/snapshots/, so a test can check a whole screen at once:
+//!
+//! ```text
+//! 8x2
+//! |[bold red]Error:[/]  |
+//! |[[x] ok  |
+//! cursor 3,1
+//! ```
+
+use markup::style_spec;
+use termrect::{HasSize, TermRect};
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Render the TermRect in the snapshot format.
+pub fn render_snapshot(tr: &TermRect) -> String {
+    let (w, h) = tr.size();
+    let mut out = format!("{}x{}\n", w, h);
+    for line in tr.lines() {
+        out.push('|');
+        for (_, style, text) in line.runs() {
+            let text = text.replace('[', "[[");
+            let spec = style_spec(style);
+            if spec.is_empty() {
+                out += &text;
+            } else {
+                out += &format!("[{}]{}[/]", spec, text);
+            }
+        }
+        out.push('|');
+        if line.wrapped {
+            out += " wrapped";
+        }
+        out.push('\n');
+    }
+    let cursor = tr.cursor();
+    if cursor.visible {
+        out += &format!("cursor {},{}\n", cursor.pos.0, cursor.pos.1);
+    }
+    out
+}
+
+/// A line by line diff of old and new, with - before removed lines and +
+/// before added ones.
+fn diff(old: &str, new: &str) -> String {
+    let (a, b): (Vec<_>, Vec<_>) = (old.lines().collect(), new.lines().collect());
+    // lcs[i][j] is the length of the longest common subsequence of a[i..]
+    // and b[j..].
+    let mut lcs = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            lcs[i][j] = if a[i] == b[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+    let mut out = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            out += &format!("  {}\n", a[i]);
+            i += 1;
+            j += 1;
+        } else if i < a.len() && (j == b.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            out += &format!("- {}\n", a[i]);
+            i += 1;
+        } else {
+            out += &format!("+ {}\n", b[j]);
+            j += 1;
+        }
+    }
+    out
+}
+
+/// Compare the TermRect with the snapshot in the file. On a mismatch, or if
+/// there's no file yet, the new snapshot is written next to it with .new on
+/// the end, and the error says what's different. With UPDATE_SNAPSHOTS=1 in
+/// the environment, the file is overwritten instead.
+pub fn check_snapshot<P: AsRef<Path>>(tr: &TermRect, path: P) -> Result<(), String> {
+    let path = path.as_ref();
+    let new = render_snapshot(tr);
+    let new_path = path.with_file_name(format!(
+        "{}.new",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let write = |p: &Path| -> Result<(), String> {
+        if let Some(dir) = p.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(p, &new).map_err(|e| format!("writing {}: {}", p.display(), e))
+    };
+
+    if env::var_os("UPDATE_SNAPSHOTS").as_ref().map(|v| v == "1") == Some(true) {
+        let _ = fs::remove_file(&new_path);
+        return write(path);
+    }
+    let old = match fs::read_to_string(path) {
+        Ok(old) => old,
+        Err(_) => {
+            write(&new_path)?;
+            return Err(format!(
+                "no snapshot at {}, wrote {}:\n{}",
+                path.display(),
+                new_path.display(),
+                new
+            ));
+        }
+    };
+    if old == new {
+        let _ = fs::remove_file(&new_path);
+        return Ok(());
+    }
+    write(&new_path)?;
+    Err(format!(
+        "snapshot {} doesn't match, wrote {}:\n{}",
+        path.display(),
+        new_path.display(),
+        diff(&old, &new)
+    ))
+}
+
+/// Assert that a TermRect matches the snapshot tests/snapshots/NAME.snap in
+/// the crate being tested. See check_snapshot.
+#[macro_export]
+macro_rules! assert_termrect_snapshot {
+    ($tr:expr, $name:expr) => {
+        if let Err(e) = $crate::testing::check_snapshot(
+            &$tr,
+            ::std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+                .join("tests")
+                .join("snapshots")
+                .join(format!("{}.snap", $name)),
+        ) {
+            panic!("{}", e);
+        }
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cursor::Cursor;
+    use style::{Color, Style, StyleAttr};
+    use styledtext::StyledText;
+    use termrect::RawPaintable;
+
+    #[test]
+    fn rendering() {
+        let mut tr = TermRect::new((6, 2));
+        let red = Style::default()
+            .set_fg(Color::Indexed(1))
+            .set(StyleAttr::Bold);
+        tr.draw_text_at((1, 0), &StyledText::new(red, "[ok]".to_string()));
+        tr.draw_str_at((0, 1), Style::default(), "a[b".to_string());
+        tr.set_wrapped(0, true);
+        tr.set_cursor(Cursor::at((3, 1)));
+        assert_eq!(
+            render_snapshot(&tr),
+            "6x2\n| [bold red][[ok][/] | wrapped\n|a[[b   |\ncursor 3,1\n"
+        );
+    }
+
+    #[test]
+    fn diffs() {
+        assert_eq!(
+            diff("a\nb\nc\n", "a\nx\nc\nd\n"),
+            "  a\n- b\n+ x\n  c\n+ d\n"
+        );
+    }
+}
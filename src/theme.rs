@@ -0,0 +1,208 @@
+use markup::{parse_spec, MarkupErrorKind};
+use style::{Style, StylePatch};
+use styledtext::StyledText;
+use termrect::{RawPaintable, Rect};
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// Theme maps role names such as "error" or "border.focused" to styles, so
+/// the look of an app can be changed in one place.
+///
+/// Role names are dotted paths. The style of a role is built up from the
+/// "default" role, then each prefix of the path in turn, so "border.focused"
+/// is "border" with the changes set for "border.focused" on top. Roles that
+/// aren't set just add nothing, so an unknown role falls back to its parent.
+#[derive(Debug, Clone, Default)]
+pub struct Theme {
+    roles: HashMap<String, StylePatch>,
+}
+
+/// What was wrong with a line of a theme.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ThemeErrorKind {
+    /// A line that isn't "role = spec".
+    MissingEquals,
+    EmptyRole,
+    /// The style spec couldn't be parsed.
+    Spec(MarkupErrorKind),
+}
+
+/// ThemeError says what was wrong with a theme, and on which line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThemeError {
+    pub kind: ThemeErrorKind,
+    /// The line number, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for ThemeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match self.kind {
+            ThemeErrorKind::MissingEquals => write!(f, "expected \"role = style\""),
+            ThemeErrorKind::EmptyRole => write!(f, "empty role name"),
+            ThemeErrorKind::Spec(ref kind) => write!(f, "{}", kind),
+        }
+    }
+}
+
+impl Error for ThemeError {}
+
+impl Theme {
+    pub fn new() -> Theme {
+        Theme::default()
+    }
+
+    /// Set the changes a role makes to its parent's style.
+    pub fn set(&mut self, role: &str, patch: StylePatch) {
+        self.roles.insert(role.to_string(), patch);
+    }
+
+    pub fn get(&self, role: &str) -> Option<&StylePatch> {
+        self.roles.get(role)
+    }
+
+    pub fn remove(&mut self, role: &str) -> Option<StylePatch> {
+        self.roles.remove(role)
+    }
+
+    /// The style for the role, falling back to its parents.
+    pub fn style(&self, role: &str) -> Style {
+        let mut style = self
+            .roles
+            .get("default")
+            .map_or(Style::default(), |p| p.apply(Style::default()));
+        let ends = role
+            .match_indices('.')
+            .map(|(i, _)| i)
+            .chain(Some(role.len()));
+        for end in ends {
+            if let Some(patch) = self.roles.get(&role[..end]) {
+                style = patch.apply(style);
+            }
+        }
+        style
+    }
+
+    /// A text styled for the role.
+    pub fn text(&self, role: &str, text: String) -> StyledText {
+        StyledText::new(self.style(role), text)
+    }
+
+    /// Parse a theme from text with one "role = spec" per line, where spec
+    /// is a style as in markup tags, such as "bold red on blue". Blank lines
+    /// and lines starting with # are skipped.
+    ///
+    /// ```text
+    /// default = white on black
+    /// error = bold red
+    /// border = blue
+    /// border.focused = bright_blue
+    /// ```
+    pub fn parse(text: &str) -> Result<Theme, ThemeError> {
+        let mut theme = Theme::new();
+        for (i, line) in text.lines().enumerate() {
+            let error = |kind| ThemeError { kind, line: i + 1 };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let eq = line
+                .find('=')
+                .ok_or_else(|| error(ThemeErrorKind::MissingEquals))?;
+            let role = line[..eq].trim();
+            if role.is_empty() {
+                return Err(error(ThemeErrorKind::EmptyRole));
+            }
+            let patch = parse_spec(&line[eq + 1..]).map_err(|e| error(ThemeErrorKind::Spec(e)))?;
+            theme.set(role, patch);
+        }
+        Ok(theme)
+    }
+}
+
+impl FromStr for Theme {
+    type Err = ThemeError;
+
+    fn from_str(s: &str) -> Result<Theme, ThemeError> {
+        Theme::parse(s)
+    }
+}
+
+/// ThemedPaint draws with styles looked up by role in a Theme. It works for
+/// anything RawPaintable.
+pub trait ThemedPaint: RawPaintable {
+    /// Draw the string at pos in the role's style. Return true if something
+    /// changed.
+    fn draw_role_at(&mut self, pos: (u32, u32), theme: &Theme, role: &str, str: String) -> bool {
+        self.draw_text_at(pos, &theme.text(role, str))
+    }
+
+    /// Fill the rect with copies of the char in the role's style. Return true
+    /// if something changed.
+    fn fill_role(&mut self, rect: Rect, c: char, theme: &Theme, role: &str) -> bool {
+        self.fill_rect(rect, c, theme.style(role))
+    }
+}
+
+impl<R: RawPaintable + ?Sized> ThemedPaint for R {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use style::{Color, StyleAttr};
+    use termrect::TermRect;
+
+    #[test]
+    fn roles() {
+        let theme: Theme = "
+            # comments and blank lines are fine
+
+            default = white on black
+            border = blue
+            border.focused = bold
+            error = bold red
+        "
+        .parse()
+        .unwrap();
+        let base = Style::default()
+            .set_fg(Color::Indexed(7))
+            .set_bg(Color::Indexed(0));
+        let border = base.set_fg(Color::Indexed(4));
+        assert_eq!(theme.style("nothing"), base);
+        assert_eq!(theme.style("border"), border);
+        assert_eq!(theme.style("border.focused"), border.set(StyleAttr::Bold));
+        assert_eq!(
+            theme.style("border.focused.left"),
+            border.set(StyleAttr::Bold)
+        );
+        assert_eq!(theme.style("border.unfocused"), border);
+
+        let mut tr = TermRect::new((4, 1));
+        assert!(tr.draw_role_at((1, 0), &theme, "error", "x".to_string()));
+        assert_eq!(
+            tr.lines()[0].texts[1].style,
+            base.set_fg(Color::Indexed(1)).set(StyleAttr::Bold)
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |text: &str| Theme::parse(text).unwrap_err();
+        assert_eq!(
+            error("a = red\nb red"),
+            ThemeError {
+                kind: ThemeErrorKind::MissingEquals,
+                line: 2
+            }
+        );
+        assert_eq!(error(" = red").kind, ThemeErrorKind::EmptyRole);
+        assert_eq!(
+            error("a = bold blurple").to_string(),
+            "line 1: unknown style \"blurple\""
+        );
+    }
+}
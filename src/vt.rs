@@ -0,0 +1,368 @@
+use cursor::{Cursor, CursorShape};
+use style::{Color, Style, StyleAttr};
+use styledtext::StyledText;
+use termrect::{HasSize, PaintableWidget, RawPaintable, Rect, TermRect};
+
+use std::mem;
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Clone, PartialEq)]
+enum State {
+    Ground,
+    Escape,
+    Csi(String),
+    // OSC, DCS and the like, which are skipped.
+    Str,
+    StrEscape,
+}
+
+/// Emulator is a small VT emulator that draws what it's fed into a TermRect.
+/// It understands what Terminal writes: cursor movement, SGR, erasing, REP,
+/// the alternate screen and cursor visibility and shape. Anything else is
+/// skipped.
+#[derive(Debug)]
+pub struct Emulator {
+    screen: TermRect,
+    // The main screen, while the alternate screen is shown.
+    main: Option<TermRect>,
+    // x can be the width, after printing in the last column. The next char
+    // then goes on the next line.
+    pos: (u32, u32),
+    saved_pos: (u32, u32),
+    style: Style,
+    last_char: Option<char>,
+    cursor: Cursor,
+    state: State,
+}
+
+impl Emulator {
+    pub fn new(size: (u32, u32)) -> Emulator {
+        Emulator {
+            screen: TermRect::new(size),
+            main: None,
+            pos: (0, 0),
+            saved_pos: (0, 0),
+            style: Style::default(),
+            last_char: None,
+            cursor: Cursor {
+                visible: true,
+                ..Cursor::default()
+            },
+            state: State::Ground,
+        }
+    }
+
+    /// The screen, with its cursor set to the emulator's.
+    pub fn screen(&self) -> &TermRect {
+        &self.screen
+    }
+
+    pub fn into_screen(self) -> TermRect {
+        self.screen
+    }
+
+    /// Change the size, keeping what fits.
+    pub fn resize(&mut self, size: (u32, u32)) {
+        let mut screen = TermRect::new(size);
+        self.screen.draw_into(&mut screen, (0, 0));
+        self.screen = screen;
+        self.pos = (
+            self.pos.0.min(size.0),
+            self.pos.1.min(size.1.saturating_sub(1)),
+        );
+        self.update_cursor();
+    }
+
+    pub fn feed(&mut self, text: &str) {
+        for c in text.chars() {
+            self.feed_char(c);
+        }
+        self.update_cursor();
+    }
+
+    fn feed_char(&mut self, c: char) {
+        match mem::replace(&mut self.state, State::Ground) {
+            State::Ground => self.ground(c),
+            State::Escape => self.escape(c),
+            State::Csi(mut params) => {
+                if ('\x40'..='\x7e').contains(&c) {
+                    self.csi(&params, c);
+                } else if c == '\x1b' {
+                    self.state = State::Escape;
+                } else {
+                    params.push(c);
+                    self.state = State::Csi(params);
+                }
+            }
+            State::Str => {
+                self.state = match c {
+                    '\x07' => State::Ground,
+                    '\x1b' => State::StrEscape,
+                    _ => State::Str,
+                }
+            }
+            State::StrEscape => {
+                if c != '\\' {
+                    self.state = State::Str;
+                }
+            }
+        }
+    }
+
+    fn ground(&mut self, c: char) {
+        let (width, _) = self.screen.size();
+        match c {
+            '\x1b' => self.state = State::Escape,
+            '\r' => self.pos.0 = 0,
+            '\n' => self.line_feed(),
+            '\x08' => self.pos.0 = self.pos.0.min(width.saturating_sub(1)).saturating_sub(1),
+            '\t' => self.pos.0 = ((self.pos.0 / 8 + 1) * 8).min(width.saturating_sub(1)),
+            c if c.is_control() => {}
+            c => self.print(c),
+        }
+    }
+
+    fn escape(&mut self, c: char) {
+        match c {
+            '[' => self.state = State::Csi(String::new()),
+            ']' | 'P' | '_' | '^' | 'X' => self.state = State::Str,
+            '7' => self.saved_pos = self.pos,
+            '8' => self.pos = self.saved_pos,
+            'c' => *self = Emulator::new(self.screen.size()),
+            // Intermediate bytes, as in ESC ( B, are followed by one more.
+            ' '..='/' => self.state = State::Escape,
+            _ => {}
+        }
+    }
+
+    fn print(&mut self, c: char) {
+        let w = UnicodeWidthChar::width(c).unwrap_or(0) as u32;
+        if w == 0 {
+            return;
+        }
+        let (width, _) = self.screen.size();
+        if self.pos.0 + w > width {
+            self.screen.set_wrapped(self.pos.1, true);
+            self.pos.0 = 0;
+            self.line_feed();
+        }
+        let text = StyledText::new(self.style, c.to_string());
+        self.screen.draw_text_at(self.pos, &text);
+        self.pos.0 += w;
+        self.last_char = Some(c);
+    }
+
+    fn line_feed(&mut self) {
+        let (_, height) = self.screen.size();
+        if self.pos.1 + 1 >= height {
+            self.screen.scroll_up(self.blank());
+        } else {
+            self.pos.1 += 1;
+        }
+    }
+
+    /// The style erased cells get: the current background only.
+    fn blank(&self) -> Style {
+        Style::default().set_bg(self.style.bg())
+    }
+
+    fn erase(&mut self, x0: u32, x1: u32, y0: u32, y1: u32) {
+        let rect = Rect::new((x0, y0), (x1.saturating_sub(x0), y1.saturating_sub(y0)));
+        let blank = self.blank();
+        self.screen.clear_rect(rect, blank);
+    }
+
+    fn csi(&mut self, params: &str, fin: char) {
+        let (width, height) = self.screen.size();
+        let private = params.starts_with('?');
+        let intermediate = params.ends_with(' ');
+        let nums: Vec<u32> = params
+            .trim_start_matches('?')
+            .trim_end_matches(' ')
+            .split(';')
+            .map(|p| p.parse().unwrap_or(0))
+            .collect();
+        // The nth param, with 0 or missing meaning 1.
+        let n = |i: usize| nums.get(i).cloned().unwrap_or(0).max(1);
+        let (x, y) = (self.pos.0.min(width.saturating_sub(1)), self.pos.1);
+        match fin {
+            'H' | 'f' => {
+                self.pos = (
+                    (n(1) - 1).min(width.saturating_sub(1)),
+                    (n(0) - 1).min(height.saturating_sub(1)),
+                )
+            }
+            'A' => self.pos.1 = y.saturating_sub(n(0)),
+            'B' => self.pos.1 = y.saturating_add(n(0)).min(height.saturating_sub(1)),
+            'C' => self.pos.0 = x.saturating_add(n(0)).min(width.saturating_sub(1)),
+            'D' => self.pos.0 = x.saturating_sub(n(0)),
+            'G' => self.pos.0 = (n(0) - 1).min(width.saturating_sub(1)),
+            'd' => self.pos.1 = (n(0) - 1).min(height.saturating_sub(1)),
+            'J' => match nums[0] {
+                0 => {
+                    self.erase(x, width, y, y + 1);
+                    self.erase(0, width, y + 1, height);
+                }
+                1 => {
+                    self.erase(0, width, 0, y);
+                    self.erase(0, x + 1, y, y + 1);
+                }
+                _ => self.erase(0, width, 0, height),
+            },
+            'K' => match nums[0] {
+                0 => self.erase(x, width, y, y + 1),
+                1 => self.erase(0, x + 1, y, y + 1),
+                _ => self.erase(0, width, y, y + 1),
+            },
+            'X' => self.erase(x, x.saturating_add(n(0)), y, y + 1),
+            'b' => {
+                if let Some(c) = self.last_char {
+                    // More than a screenful would only overwrite itself.
+                    for _ in 0..n(0).min(width.saturating_mul(height)) {
+                        self.print(c);
+                    }
+                }
+            }
+            'm' if !private => self.sgr(&nums),
+            'q' if intermediate => {
+                let (shape, blinking) = match nums[0] {
+                    0 | 1 => (CursorShape::Block, true),
+                    2 => (CursorShape::Block, false),
+                    3 => (CursorShape::Underline, true),
+                    4 => (CursorShape::Underline, false),
+                    5 => (CursorShape::Bar, true),
+                    _ => (CursorShape::Bar, false),
+                };
+                self.cursor.shape = shape;
+                self.cursor.blinking = blinking;
+            }
+            'h' | 'l' if private => {
+                let on = fin == 'h';
+                for &mode in &nums {
+                    match mode {
+                        25 => self.cursor.visible = on,
+                        47 | 1047 | 1049 => self.alternate_screen(on),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn alternate_screen(&mut self, on: bool) {
+        if on && self.main.is_none() {
+            let alt = TermRect::new(self.screen.size());
+            self.main = Some(mem::replace(&mut self.screen, alt));
+        } else if !on {
+            if let Some(main) = self.main.take() {
+                self.screen = main;
+            }
+        }
+    }
+
+    fn sgr(&mut self, nums: &[u32]) {
+        let mut i = 0;
+        while i < nums.len() {
+            let s = self.style;
+            self.style = match nums[i] {
+                0 => Style::default(),
+                1 => s.set(StyleAttr::Bold),
+                2 => s.set(StyleAttr::Faint),
+                3 => s.set(StyleAttr::Italic),
+                4 => s.set(StyleAttr::Underline),
+                7 => s.set(StyleAttr::Invert),
+                9 => s.set(StyleAttr::CrossedOut),
+                22 => s.clear(StyleAttr::Bold).clear(StyleAttr::Faint),
+                23 => s.clear(StyleAttr::Italic),
+                24 => s.clear(StyleAttr::Underline),
+                27 => s.clear(StyleAttr::Invert),
+                29 => s.clear(StyleAttr::CrossedOut),
+                n @ 30..=37 => s.set_fg(Color::Indexed(n as u16 - 30)),
+                n @ 40..=47 => s.set_bg(Color::Indexed(n as u16 - 40)),
+                n @ 90..=97 => s.set_fg(Color::Indexed(n as u16 - 90 + 8)),
+                n @ 100..=107 => s.set_bg(Color::Indexed(n as u16 - 100 + 8)),
+                39 => s.set_fg(Color::Default),
+                49 => s.set_bg(Color::Default),
+                n @ 38 | n @ 48 => {
+                    let color = match nums.get(i + 1) {
+                        Some(5) if i + 2 < nums.len() => {
+                            i += 2;
+                            Color::Indexed(nums[i] as u16)
+                        }
+                        Some(2) if i + 4 < nums.len() => {
+                            i += 4;
+                            Color::RGB(nums[i - 2] as u8, nums[i - 1] as u8, nums[i] as u8)
+                        }
+                        _ => break,
+                    };
+                    if n == 38 {
+                        s.set_fg(color)
+                    } else {
+                        s.set_bg(color)
+                    }
+                }
+                _ => s,
+            };
+            i += 1;
+        }
+    }
+
+    fn update_cursor(&mut self) {
+        let (width, _) = self.screen.size();
+        let cursor = Cursor {
+            pos: (self.pos.0.min(width.saturating_sub(1)), self.pos.1),
+            ..self.cursor
+        };
+        self.screen.set_cursor(cursor);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use testing::render_snapshot;
+
+    #[test]
+    fn emulator() {
+        let mut e = Emulator::new((6, 3));
+        e.feed("\x1b[2;3Hab\x1b[2b\x1b[1;31mX\x1b[mhello");
+        assert_eq!(
+            render_snapshot(e.screen()),
+            "6x3\n|      |\n|  abbb| wrapped\n|[bold red]X[/]hello|\ncursor 5,2\n"
+        );
+
+        // scrolls off the top at the bottom
+        e.feed("\r\n12\x1b[1;1H\x1b[K\x1b[2;2H\x1b[2X");
+        assert_eq!(
+            render_snapshot(e.screen()),
+            "6x3\n|      | wrapped\n|[bold red]X[/]  llo|\n|12    |\ncursor 1,1\n"
+        );
+
+        e.feed("\x1b[?1049h\x1b]0;title\x07\x1b[?25l\x1b[6 q");
+        assert_eq!(
+            render_snapshot(e.screen()),
+            "6x3\n|      |\n|      |\n|      |\n"
+        );
+        let cursor = e.screen().cursor();
+        assert!(!cursor.visible);
+        assert_eq!(cursor.shape, CursorShape::Bar);
+        e.feed("\x1b[?1049l");
+        assert_eq!(
+            render_snapshot(e.screen()),
+            "6x3\n|      | wrapped\n|[bold red]X[/]  llo|\n|12    |\n"
+        );
+    }
+
+    #[test]
+    fn huge_counts() {
+        let mut e = Emulator::new((4, 2));
+        e.feed("ab\x1b[4294967295X\x1b[4294967295B\x1b[4294967295Cc");
+        assert_eq!(e.screen().cursor().pos, (3, 1));
+        e.feed("\x1b[1;1Hx\x1b[4294967295b");
+        assert_eq!(
+            render_snapshot(e.screen()),
+            "4x2\n|xxxx| wrapped\n|x   |\ncursor 1,1\n"
+        );
+    }
+}
@@ -0,0 +1,18 @@
+#![cfg(feature = "testing")]
+
+#[macro_use]
+extern crate termrect;
+
+use termrect::paragraph::{Alignment, Paragraph};
+use termrect::{parse_markup, TermRect};
+
+#[test]
+fn paragraph() {
+    let text =
+        parse_markup("[bold]Snapshots[/] show a whole [red on blue]screen[/] at once.").unwrap();
+    let mut tr = TermRect::new((12, 5));
+    Paragraph::from(text)
+        .set_alignment(Alignment::Center)
+        .draw_into(&mut tr, (0, 0), 12);
+    assert_termrect_snapshot!(tr, "paragraph");
+}